@@ -0,0 +1,48 @@
+//! Measures the cost of reading the cached page size.
+//!
+//! Once the cache is populated, `page_size::get()` is a relaxed load of the
+//! cache word, a test-and-branch on zero and a shift (with the `init-array`
+//! feature on Linux, just a relaxed load). To see the generated code, build
+//! this benchmark with `cargo rustc --release --bench get -- --emit asm` and
+//! look for `read_page_size` in `target/release/deps/get-*.s`.
+
+extern crate page_size;
+
+use std::hint::black_box;
+use std::time::Instant;
+
+const ITERATIONS: u32 = 100_000_000;
+
+#[inline(never)]
+fn read_page_size() -> usize {
+    page_size::get()
+}
+
+#[inline(never)]
+fn read_page_size_fallible() -> usize {
+    page_size::try_get()
+        .map(|page_size| page_size.bytes())
+        .unwrap_or(0)
+}
+
+fn bench(name: &str, f: fn() -> usize) {
+    // Populate the cache outside of the measured loop.
+    black_box(f());
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(f());
+    }
+    let elapsed = start.elapsed();
+
+    println!(
+        "{:<12} {:>8.3} ns/iter",
+        name,
+        elapsed.as_secs_f64() * 1e9 / f64::from(ITERATIONS)
+    );
+}
+
+fn main() {
+    bench("get", read_page_size);
+    bench("try_get", read_page_size_fallible);
+}
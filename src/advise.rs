@@ -0,0 +1,212 @@
+//! Advising the kernel about the use of pages (`madvise`).
+
+use core::ops::Range;
+use core::ptr;
+
+use mapping::page_range;
+use unix::sys;
+use Error;
+
+/// Advice about the use of a range of pages, given with [`advise`].
+///
+/// Some advice values need a recent kernel or one built with a particular
+/// feature; the version they appeared in is noted for each.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Advice {
+    /// `MADV_DONTNEED`: free the pages now. Private anonymous pages read
+    /// back as zeros afterwards.
+    DontNeed,
+    /// `MADV_FREE`: let the kernel free the pages lazily, under memory
+    /// pressure. Pages that are written to before that are kept. Linux 4.5.
+    Free,
+    /// `MADV_WILLNEED`: read the pages ahead, they will be accessed soon.
+    WillNeed,
+    /// `MADV_HUGEPAGE`: back the pages with transparent huge pages.
+    /// Requires THP support.
+    HugePage,
+    /// `MADV_NOHUGEPAGE`: do not back the pages with transparent huge
+    /// pages. Requires THP support.
+    NoHugePage,
+    /// `MADV_COLD`: deactivate the pages, making them the first to be
+    /// reclaimed. Linux 5.4.
+    Cold,
+    /// `MADV_PAGEOUT`: reclaim the pages now. Linux 5.4.
+    PageOut,
+    /// `MADV_POPULATE_READ`: fault the pages in readable, as reading them
+    /// would. Linux 5.14.
+    PopulateRead,
+    /// `MADV_POPULATE_WRITE`: fault the pages in writable, as writing them
+    /// would. Linux 5.14.
+    PopulateWrite,
+    /// `MADV_DONTDUMP`: leave the pages out of core dumps.
+    DontDump,
+    /// `MADV_DODUMP`: undo [`DontDump`](Advice::DontDump).
+    DoDump,
+    /// `MADV_DONTFORK`: do not make the pages available to child processes.
+    DontFork,
+    /// `MADV_DOFORK`: undo [`DontFork`](Advice::DontFork).
+    DoFork,
+    /// `MADV_WIPEONFORK`: give child processes zero-filled pages instead of
+    /// a copy. Linux 4.14.
+    WipeOnFork,
+    /// `MADV_KEEPONFORK`: undo [`WipeOnFork`](Advice::WipeOnFork).
+    /// Linux 4.14.
+    KeepOnFork,
+    /// `MADV_MERGEABLE`: let KSM merge identical pages. Requires KSM
+    /// support.
+    Mergeable,
+    /// `MADV_UNMERGEABLE`: undo [`Mergeable`](Advice::Mergeable). Requires
+    /// KSM support.
+    Unmergeable,
+}
+
+impl Advice {
+    /// Returns `true` if the running kernel knows this advice.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate page_size;
+    /// use page_size::Advice;
+    ///
+    /// assert!(Advice::DontNeed.is_supported());
+    /// ```
+    pub fn is_supported(self) -> bool {
+        // The kernel validates the advice before the range, and accepts any
+        // empty range.
+        unsafe { sys::madvise(ptr::null_mut(), 0, self.code()) }.is_ok()
+    }
+
+    fn code(self) -> i32 {
+        match self {
+            Advice::DontNeed => sys::MADV_DONTNEED,
+            Advice::Free => sys::MADV_FREE,
+            Advice::WillNeed => sys::MADV_WILLNEED,
+            Advice::HugePage => sys::MADV_HUGEPAGE,
+            Advice::NoHugePage => sys::MADV_NOHUGEPAGE,
+            Advice::Cold => sys::MADV_COLD,
+            Advice::PageOut => sys::MADV_PAGEOUT,
+            Advice::PopulateRead => sys::MADV_POPULATE_READ,
+            Advice::PopulateWrite => sys::MADV_POPULATE_WRITE,
+            Advice::DontDump => sys::MADV_DONTDUMP,
+            Advice::DoDump => sys::MADV_DODUMP,
+            Advice::DontFork => sys::MADV_DONTFORK,
+            Advice::DoFork => sys::MADV_DOFORK,
+            Advice::WipeOnFork => sys::MADV_WIPEONFORK,
+            Advice::KeepOnFork => sys::MADV_KEEPONFORK,
+            Advice::Mergeable => sys::MADV_MERGEABLE,
+            Advice::Unmergeable => sys::MADV_UNMERGEABLE,
+        }
+    }
+
+    fn operation(self) -> &'static str {
+        match self {
+            Advice::DontNeed => "madvise(MADV_DONTNEED)",
+            Advice::Free => "madvise(MADV_FREE)",
+            Advice::WillNeed => "madvise(MADV_WILLNEED)",
+            Advice::HugePage => "madvise(MADV_HUGEPAGE)",
+            Advice::NoHugePage => "madvise(MADV_NOHUGEPAGE)",
+            Advice::Cold => "madvise(MADV_COLD)",
+            Advice::PageOut => "madvise(MADV_PAGEOUT)",
+            Advice::PopulateRead => "madvise(MADV_POPULATE_READ)",
+            Advice::PopulateWrite => "madvise(MADV_POPULATE_WRITE)",
+            Advice::DontDump => "madvise(MADV_DONTDUMP)",
+            Advice::DoDump => "madvise(MADV_DODUMP)",
+            Advice::DontFork => "madvise(MADV_DONTFORK)",
+            Advice::DoFork => "madvise(MADV_DOFORK)",
+            Advice::WipeOnFork => "madvise(MADV_WIPEONFORK)",
+            Advice::KeepOnFork => "madvise(MADV_KEEPONFORK)",
+            Advice::Mergeable => "madvise(MADV_MERGEABLE)",
+            Advice::Unmergeable => "madvise(MADV_UNMERGEABLE)",
+        }
+    }
+}
+
+/// Gives the kernel `advice` about the pages of the address range `range`.
+///
+/// `range.start` must lie on a page boundary; `range.end` is rounded up to
+/// the next one. Fails with an [`Error::Unaligned`] or
+/// [`Error::OutOfRange`] on invalid ranges, with an [`Error::Unsupported`]
+/// if the running kernel does not know `advice`, and with an [`Error::Os`]
+/// if the kernel refuses it for the range, for example because part of it is
+/// not mapped (`ENOMEM`) or `advice` does not apply to that kind of mapping
+/// (`EINVAL`).
+///
+/// # Safety
+///
+/// Some advice changes the contents of memory: [`Advice::DontNeed`] and
+/// [`Advice::Free`] discard them, and [`Advice::DontFork`] and
+/// [`Advice::WipeOnFork`] change what child processes see. The caller must
+/// own the pages of `range` and be prepared for these effects.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate page_size;
+/// use page_size::{Advice, PageMapping};
+///
+/// let mut mapping = PageMapping::new(1).unwrap();
+/// mapping[0] = 1;
+/// let start = mapping.as_ptr() as usize;
+/// unsafe { page_size::advise(start..start + mapping.len(), Advice::DontNeed).unwrap() };
+/// assert_eq!(mapping[0], 0);
+/// ```
+pub unsafe fn advise(range: Range<usize>, advice: Advice) -> Result<(), Error> {
+    let range = page_range(range)?;
+    match sys::madvise(
+        range.start as *mut u8,
+        range.end - range.start,
+        advice.code(),
+    ) {
+        Ok(()) => Ok(()),
+        Err(sys::EINVAL) if !advice.is_supported() => Err(Error::Unsupported {
+            operation: advice.operation(),
+        }),
+        Err(errno) => Err(Error::Os {
+            operation: advice.operation(),
+            errno,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use PageMapping;
+
+    #[test]
+    fn test_advise() {
+        let page_size = ::get();
+        let mut mapping = PageMapping::pages(2).unwrap();
+        let start = mapping.as_ptr() as usize;
+        mapping[0] = 1;
+        mapping[page_size] = 1;
+
+        unsafe {
+            advise(start..start + 1, Advice::DontNeed).unwrap();
+            advise(start..start + 2 * page_size, Advice::DontDump).unwrap();
+            advise(start..start + 2 * page_size, Advice::DoDump).unwrap();
+        }
+        assert_eq!((mapping[0], mapping[page_size]), (0, 1));
+
+        assert_eq!(
+            unsafe { advise(start + 1..start + 2, Advice::WillNeed) },
+            Err(Error::Unaligned { value: start + 1 })
+        );
+        // Nothing is ever mapped at address 0.
+        assert_eq!(
+            unsafe { advise(0..page_size, Advice::WillNeed) },
+            Err(Error::Os {
+                operation: "madvise(MADV_WILLNEED)",
+                errno: sys::ENOMEM
+            })
+        );
+    }
+
+    #[test]
+    fn test_is_supported() {
+        assert!(Advice::DontNeed.is_supported());
+        assert!(Advice::DontDump.is_supported());
+    }
+}
@@ -0,0 +1,327 @@
+//! Page alignment arithmetic.
+
+use core::alloc::Layout;
+use core::ptr::NonNull;
+
+use PageSize;
+
+/// Values that can be aligned to a page boundary.
+///
+/// This is implemented for integer addresses and offsets (`usize`, `u64`) and
+/// for pointers. Rounding up is checked: it returns `None` instead of
+/// wrapping around the end of the address space.
+pub trait Align: Copy {
+    /// The result of [`page_align_down`](Align::page_align_down).
+    ///
+    /// This is `Self` for every implementation except `NonNull<T>`, which
+    /// cannot represent an address rounded down to zero.
+    type Down;
+
+    /// Rounds `self` up to the next multiple of `page_size`, returning `None`
+    /// on overflow.
+    fn page_align_up(self, page_size: PageSize) -> Option<Self>;
+
+    /// Rounds `self` down to the previous multiple of `page_size`.
+    fn page_align_down(self, page_size: PageSize) -> Self::Down;
+
+    /// Returns `true` if `self` is a multiple of `page_size`.
+    fn is_page_aligned(self, page_size: PageSize) -> bool;
+}
+
+impl Align for usize {
+    type Down = usize;
+
+    #[inline]
+    fn page_align_up(self, page_size: PageSize) -> Option<usize> {
+        self.checked_add(page_size.mask())
+            .map(|value| value & !page_size.mask())
+    }
+
+    #[inline]
+    fn page_align_down(self, page_size: PageSize) -> usize {
+        self & !page_size.mask()
+    }
+
+    #[inline]
+    fn is_page_aligned(self, page_size: PageSize) -> bool {
+        self & page_size.mask() == 0
+    }
+}
+
+impl Align for u64 {
+    type Down = u64;
+
+    #[inline]
+    fn page_align_up(self, page_size: PageSize) -> Option<u64> {
+        let mask = page_size.mask() as u64;
+        self.checked_add(mask).map(|value| value & !mask)
+    }
+
+    #[inline]
+    fn page_align_down(self, page_size: PageSize) -> u64 {
+        self & !(page_size.mask() as u64)
+    }
+
+    #[inline]
+    fn is_page_aligned(self, page_size: PageSize) -> bool {
+        self & page_size.mask() as u64 == 0
+    }
+}
+
+// Pointers are moved with `wrapping_add`/`wrapping_sub` rather than rebuilt
+// from integers so that they keep their provenance.
+
+impl<T> Align for *const T {
+    type Down = *const T;
+
+    #[inline]
+    fn page_align_up(self, page_size: PageSize) -> Option<*const T> {
+        let addr = self as usize;
+        addr.page_align_up(page_size)
+            .map(|aligned| (self as *const u8).wrapping_add(aligned - addr) as *const T)
+    }
+
+    #[inline]
+    fn page_align_down(self, page_size: PageSize) -> *const T {
+        (self as *const u8).wrapping_sub(self as usize % page_size) as *const T
+    }
+
+    #[inline]
+    fn is_page_aligned(self, page_size: PageSize) -> bool {
+        (self as usize).is_page_aligned(page_size)
+    }
+}
+
+impl<T> Align for *mut T {
+    type Down = *mut T;
+
+    #[inline]
+    fn page_align_up(self, page_size: PageSize) -> Option<*mut T> {
+        (self as *const T)
+            .page_align_up(page_size)
+            .map(|ptr| ptr as *mut T)
+    }
+
+    #[inline]
+    fn page_align_down(self, page_size: PageSize) -> *mut T {
+        (self as *const T).page_align_down(page_size) as *mut T
+    }
+
+    #[inline]
+    fn is_page_aligned(self, page_size: PageSize) -> bool {
+        (self as usize).is_page_aligned(page_size)
+    }
+}
+
+impl<T> Align for NonNull<T> {
+    type Down = Option<NonNull<T>>;
+
+    #[inline]
+    fn page_align_up(self, page_size: PageSize) -> Option<NonNull<T>> {
+        // Rounding a non-null address up either overflows or stays non-null.
+        self.as_ptr()
+            .page_align_up(page_size)
+            .and_then(NonNull::new)
+    }
+
+    #[inline]
+    fn page_align_down(self, page_size: PageSize) -> Option<NonNull<T>> {
+        NonNull::new(self.as_ptr().page_align_down(page_size))
+    }
+
+    #[inline]
+    fn is_page_aligned(self, page_size: PageSize) -> bool {
+        self.as_ptr().is_page_aligned(page_size)
+    }
+}
+
+impl PageSize {
+    /// Rounds `value` up to the next page boundary, returning `None` on
+    /// overflow.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate page_size;
+    /// let page_size = page_size::PageSize::new(4096).unwrap();
+    /// assert_eq!(page_size.align_up(1usize), Some(4096));
+    /// assert_eq!(page_size.align_up(4096usize), Some(4096));
+    /// assert_eq!(page_size.align_up(usize::MAX), None);
+    /// ```
+    #[inline]
+    pub fn align_up<A: Align>(self, value: A) -> Option<A> {
+        value.page_align_up(self)
+    }
+
+    /// Rounds `value` down to the previous page boundary.
+    #[inline]
+    pub fn align_down<A: Align>(self, value: A) -> A::Down {
+        value.page_align_down(self)
+    }
+
+    /// Returns `true` if `value` lies on a page boundary.
+    #[inline]
+    pub fn is_aligned<A: Align>(self, value: A) -> bool {
+        value.is_page_aligned(self)
+    }
+
+    /// Returns the number of pages needed to hold `bytes` bytes.
+    ///
+    /// Unlike `(bytes + page_size - 1) / page_size`, this cannot overflow.
+    #[inline]
+    pub fn pages_needed(self, bytes: usize) -> usize {
+        (bytes >> self.shift()) + (bytes & self.mask() != 0) as usize
+    }
+}
+
+/// Rounds `value` up to the next multiple of the system's page size,
+/// returning `None` on overflow.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate page_size;
+/// assert_eq!(page_size::align_up(1usize), Some(page_size::get()));
+/// ```
+#[inline]
+pub fn align_up<A: Align>(value: A) -> Option<A> {
+    PageSize::get().align_up(value)
+}
+
+/// Rounds `value` down to the previous multiple of the system's page size.
+#[inline]
+pub fn align_down<A: Align>(value: A) -> A::Down {
+    PageSize::get().align_down(value)
+}
+
+/// Returns `true` if `value` is a multiple of the system's page size.
+#[inline]
+pub fn is_aligned<A: Align>(value: A) -> bool {
+    PageSize::get().is_aligned(value)
+}
+
+/// Returns the number of pages of the system's page size needed to hold
+/// `bytes` bytes.
+#[inline]
+pub fn pages_needed(bytes: usize) -> usize {
+    PageSize::get().pages_needed(bytes)
+}
+
+/// Rounds `value` up to the next multiple of the system's allocation
+/// granularity, returning `None` on overflow.
+#[inline]
+pub fn align_up_granularity<A: Align>(value: A) -> Option<A> {
+    PageSize::get_granularity().align_up(value)
+}
+
+/// Rounds `value` down to the previous multiple of the system's allocation
+/// granularity.
+#[inline]
+pub fn align_down_granularity<A: Align>(value: A) -> A::Down {
+    PageSize::get_granularity().align_down(value)
+}
+
+/// Returns `true` if `value` is a multiple of the system's allocation
+/// granularity.
+#[inline]
+pub fn is_aligned_granularity<A: Align>(value: A) -> bool {
+    PageSize::get_granularity().is_aligned(value)
+}
+
+/// Page alignment for [`Layout`].
+pub trait LayoutExt {
+    /// Returns a layout whose size and alignment are multiples of
+    /// `page_size`, or `None` if the padded size overflows.
+    fn pad_to(&self, page_size: PageSize) -> Option<Layout>;
+
+    /// Returns a layout padded to the system's page size.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate page_size;
+    /// use std::alloc::Layout;
+    /// use page_size::LayoutExt;
+    ///
+    /// let layout = Layout::new::<[u8; 100]>().pad_to_page().unwrap();
+    /// assert_eq!(layout.size(), page_size::get());
+    /// assert_eq!(layout.align(), page_size::get());
+    /// ```
+    fn pad_to_page(&self) -> Option<Layout> {
+        self.pad_to(PageSize::get())
+    }
+
+    /// Returns a layout padded to the system's allocation granularity.
+    fn pad_to_granularity(&self) -> Option<Layout> {
+        self.pad_to(PageSize::get_granularity())
+    }
+}
+
+impl LayoutExt for Layout {
+    fn pad_to(&self, page_size: PageSize) -> Option<Layout> {
+        let align = self.align().max(page_size.bytes());
+        let size = self.size().page_align_up(page_size)?;
+        Layout::from_size_align(size, align).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_align_integers() {
+        let page_size = PageSize::new(4096).unwrap();
+
+        assert_eq!(page_size.align_up(0usize), Some(0));
+        assert_eq!(page_size.align_up(4097usize), Some(8192));
+        assert_eq!(
+            page_size.align_up(usize::MAX - 4095),
+            Some(usize::MAX - 4095)
+        );
+        assert_eq!(page_size.align_up(usize::MAX - 4094), None);
+        assert_eq!(page_size.align_down(8191usize), 4096);
+        assert!(page_size.is_aligned(8192usize));
+        assert!(!page_size.is_aligned(8193usize));
+
+        assert_eq!(page_size.align_up(4097u64), Some(8192));
+        assert_eq!(page_size.align_up(u64::MAX), None);
+        assert_eq!(page_size.align_down(u64::MAX), u64::MAX - 4095);
+
+        assert_eq!(page_size.pages_needed(0), 0);
+        assert_eq!(page_size.pages_needed(1), 1);
+        assert_eq!(page_size.pages_needed(8192), 2);
+        assert_eq!(page_size.pages_needed(usize::MAX), (usize::MAX >> 12) + 1);
+    }
+
+    #[test]
+    fn test_align_pointers() {
+        let page_size = PageSize::get();
+        let buf = [0u8; 3];
+        let ptr = &buf[1] as *const u8;
+
+        let down = page_size.align_down(ptr);
+        assert!(page_size.is_aligned(down));
+        assert!(down <= ptr && ptr as usize - (down as usize) < page_size.bytes());
+        let up = page_size.align_up(ptr as *mut u8).unwrap();
+        assert!(page_size.is_aligned(up));
+
+        let low = NonNull::new(16 as *mut u8).unwrap();
+        assert_eq!(page_size.align_down(low), None);
+        assert_eq!(
+            page_size.align_up(low).map(|ptr| ptr.as_ptr() as usize),
+            Some(page_size.bytes())
+        );
+    }
+
+    #[test]
+    fn test_layout_pad_to() {
+        let page_size = PageSize::new(4096).unwrap();
+        let layout = Layout::from_size_align(5000, 8).unwrap();
+        let padded = layout.pad_to(page_size).unwrap();
+        assert_eq!((padded.size(), padded.align()), (8192, 4096));
+
+        let huge = Layout::from_size_align(isize::MAX as usize - 100, 1).unwrap();
+        assert_eq!(huge.pad_to(page_size), None);
+    }
+}
@@ -0,0 +1,216 @@
+//! A debugging allocator that puts every allocation next to a guard page.
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr;
+
+use unix::sys;
+use {try_get, Align, PageSize};
+
+/// A [`GlobalAlloc`] that gives every allocation its own pages, next to an
+/// inaccessible guard page, and never reuses memory, in the manner of
+/// Electric Fence.
+///
+/// In the default overflow mode, each allocation ends where its last page
+/// ends, so reading or writing past its end faults at once. In underflow
+/// mode, each allocation starts where its first page starts, so accesses
+/// before its start fault instead. Either way, the pages around the
+/// allocation are inaccessible. Freed pages are made inaccessible and kept
+/// mapped, so any use after free faults too.
+///
+/// Overruns are only caught to the byte when the size of an allocation is a
+/// multiple of its alignment: otherwise, it is placed as close to the end
+/// of its pages as alignment allows. Every allocation costs at least one
+/// page of memory and a few pages of address space, which is never given
+/// back, so this is for test suites, not production.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate page_size;
+/// use page_size::GuardPageAllocator;
+///
+/// #[global_allocator]
+/// static ALLOCATOR: GuardPageAllocator = GuardPageAllocator::new();
+///
+/// let bytes = vec![1u8; 10];
+/// // `bytes[10]` would fault, rather than read past the allocation.
+/// assert_eq!(bytes[9], 1);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GuardPageAllocator {
+    underflow: bool,
+}
+
+impl GuardPageAllocator {
+    /// Returns an allocator that catches accesses past the end of
+    /// allocations.
+    pub const fn new() -> GuardPageAllocator {
+        GuardPageAllocator { underflow: false }
+    }
+
+    /// Returns an allocator that catches accesses before the start of
+    /// allocations.
+    pub const fn underflow() -> GuardPageAllocator {
+        GuardPageAllocator { underflow: true }
+    }
+
+    /// Returns `true` if the allocator catches accesses before the start of
+    /// allocations rather than past their end.
+    #[inline]
+    pub fn is_underflow(&self) -> bool {
+        self.underflow
+    }
+}
+
+impl Default for GuardPageAllocator {
+    fn default() -> GuardPageAllocator {
+        GuardPageAllocator::new()
+    }
+}
+
+unsafe impl GlobalAlloc for GuardPageAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // Panicking could allocate, so failures return null instead.
+        let page_size = match try_get() {
+            Ok(page_size) => page_size,
+            Err(_) => return ptr::null_mut(),
+        };
+        let (size, align) = (layout.size(), layout.align());
+
+        // The allocation is surrounded by a guard page on each side. Extra
+        // alignment beyond a page takes extra pages, whole ones since both
+        // are powers of two.
+        let slack = align.saturating_sub(page_size.bytes());
+        let total = page_size
+            .checked_mul(page_size.pages_needed(size))
+            .and_then(|data| data.checked_add(slack))
+            .and_then(|len| len.checked_add(2 * page_size.bytes()));
+        let total = match total {
+            Some(total) => total,
+            None => return ptr::null_mut(),
+        };
+        let base = match sys::mmap(
+            ptr::null_mut(),
+            total,
+            sys::PROT_NONE,
+            sys::MAP_PRIVATE | sys::MAP_ANONYMOUS,
+            -1,
+            0,
+        ) {
+            Ok(base) => base as usize,
+            Err(_) => return ptr::null_mut(),
+        };
+
+        let align = PageSize::new(align).expect("alignments are powers of two");
+        let start = if self.underflow {
+            (base + page_size.bytes()).page_align_up(align)
+        } else {
+            Some((base + total - page_size.bytes() - size).page_align_down(align))
+        };
+        // Only the pages holding the allocation are made accessible.
+        let start = start.expect("the mapping holds the aligned allocation");
+        let (ptr, len) = pages(start, size, page_size);
+        if len > 0 && sys::mprotect(ptr, len, sys::PROT_READ | sys::PROT_WRITE).is_err() {
+            sys::munmap(base as *mut u8, total).ok();
+            return ptr::null_mut();
+        }
+
+        start as *mut u8
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        // Fresh anonymous pages are zero-filled.
+        self.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let page_size = PageSize::get();
+        let (ptr, len) = pages(ptr as usize, layout.size(), page_size);
+        if len == 0 {
+            return;
+        }
+
+        // Mapping fresh inaccessible pages over the allocation frees its
+        // memory, while keeping its addresses from being reused.
+        let result = sys::mmap(
+            ptr,
+            len,
+            sys::PROT_NONE,
+            sys::MAP_PRIVATE | sys::MAP_ANONYMOUS | sys::MAP_FIXED,
+            -1,
+            0,
+        );
+        debug_assert!(result.is_ok(), "mmap failed: {:?}", result);
+    }
+}
+
+// Returns the start and length of the pages holding the `size` bytes at
+// `start`.
+fn pages(start: usize, size: usize, page_size: PageSize) -> (*mut u8, usize) {
+    let end = (start + size)
+        .page_align_up(page_size)
+        .expect("allocations end inside their mapping");
+    let start = start.page_align_down(page_size);
+    (start as *mut u8, end - start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Returns the residency of the page before the `size` bytes at `ptr`,
+    // of the first and last page holding them, and of the page after them.
+    fn residency(ptr: *mut u8, size: usize) -> [bool; 4] {
+        let page_size = PageSize::get();
+        let (start, len) = pages(ptr as usize, size, page_size);
+        let start = start as usize - page_size.bytes();
+
+        let mut pages = [false; 8];
+        let mut count = 0;
+        ::for_each_page_residency(start..start + len + 2 * page_size.bytes(), |resident| {
+            pages[count] = resident;
+            count += 1;
+        })
+        .unwrap();
+        [pages[0], pages[1], pages[count - 2], pages[count - 1]]
+    }
+
+    #[test]
+    fn test_overflow() {
+        let page_size = ::get();
+        let allocator = GuardPageAllocator::new();
+
+        let layout = Layout::from_size_align(24, 8).unwrap();
+        let ptr = unsafe { allocator.alloc(layout) };
+        assert!(!ptr.is_null());
+        assert_eq!((ptr as usize + 24) % page_size, 0);
+        unsafe { ptr::write_bytes(ptr, 0xa5, 24) };
+        assert_eq!(residency(ptr, 24), [false, true, true, false]);
+
+        unsafe { allocator.dealloc(ptr, layout) };
+        assert_eq!(residency(ptr, 24), [false, false, false, false]);
+
+        let layout = Layout::from_size_align(5, 4).unwrap();
+        let ptr = unsafe { allocator.alloc_zeroed(layout) };
+        assert_eq!((ptr as usize + 8) % page_size, 0);
+        assert_eq!(unsafe { *ptr.add(4) }, 0);
+        unsafe { allocator.dealloc(ptr, layout) };
+    }
+
+    #[test]
+    fn test_underflow() {
+        let page_size = ::get();
+        let allocator = GuardPageAllocator::underflow();
+        assert!(allocator.is_underflow());
+
+        let layout = Layout::from_size_align(page_size + 1, 4 * page_size).unwrap();
+        let ptr = unsafe { allocator.alloc(layout) };
+        assert_eq!(ptr as usize % (4 * page_size), 0);
+        unsafe { ptr::write_bytes(ptr, 0xa5, page_size + 1) };
+        assert_eq!(residency(ptr, page_size + 1), [false, true, true, false]);
+        unsafe { allocator.dealloc(ptr, layout) };
+
+        let layout = Layout::from_size_align(isize::MAX as usize, 1).unwrap();
+        assert!(unsafe { allocator.alloc(layout) }.is_null());
+    }
+}
@@ -0,0 +1,146 @@
+//! Buffers that sit against a guard page, to catch reads past their ends.
+
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+
+use guard::GuardedMapping;
+use Error;
+
+/// A copy of some bytes placed so that they end exactly at a page boundary,
+/// followed by an inaccessible guard page.
+///
+/// Reading even one byte past the end of the buffer faults, which makes
+/// over-reads in parsers and decoders show up in tests and fuzzing instead
+/// of going unnoticed. With [`underflow`](BoundaryBuffer::underflow), the
+/// buffer starts right after a guard page instead, to catch reads before
+/// its start.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate page_size;
+/// use page_size::BoundaryBuffer;
+///
+/// let input = BoundaryBuffer::from(&b"GET / HTTP/1.1\r\n"[..]);
+/// assert_eq!(&input[..3], b"GET");
+/// // `input.as_ptr().add(input.len())` points into the guard page.
+/// assert_eq!((input.as_ptr() as usize + input.len()) % page_size::get(), 0);
+/// ```
+pub struct BoundaryBuffer {
+    mapping: GuardedMapping,
+    start: usize,
+    len: usize,
+}
+
+impl BoundaryBuffer {
+    /// Copies `bytes` so that they end at a page boundary followed by a
+    /// guard page.
+    ///
+    /// Fails with an [`Error::Os`] if the kernel refuses the mapping.
+    pub fn new(bytes: &[u8]) -> Result<BoundaryBuffer, Error> {
+        // Mappings cannot be empty; an empty buffer sits at the end of a page.
+        let mapping = GuardedMapping::new(bytes.len().max(1), false, true)?;
+        let start = mapping.len() - bytes.len();
+        Ok(BoundaryBuffer::copy(mapping, start, bytes))
+    }
+
+    /// Copies `bytes` so that they start at a page boundary preceded by a
+    /// guard page.
+    ///
+    /// The buffer is followed by a guard page too, but unless the length of
+    /// `bytes` is a multiple of the page size, not right away. Fails with an
+    /// [`Error::Os`] if the kernel refuses the mapping.
+    pub fn underflow(bytes: &[u8]) -> Result<BoundaryBuffer, Error> {
+        let mapping = GuardedMapping::new(bytes.len().max(1), true, true)?;
+        Ok(BoundaryBuffer::copy(mapping, 0, bytes))
+    }
+
+    fn copy(mut mapping: GuardedMapping, start: usize, bytes: &[u8]) -> BoundaryBuffer {
+        mapping[start..start + bytes.len()].copy_from_slice(bytes);
+        BoundaryBuffer {
+            mapping,
+            start,
+            len: bytes.len(),
+        }
+    }
+}
+
+impl<'a> From<&'a [u8]> for BoundaryBuffer {
+    /// Copies `bytes` so that they end at a page boundary followed by a
+    /// guard page.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the kernel refuses the mapping. Use
+    /// [`BoundaryBuffer::new`] to handle that case instead.
+    fn from(bytes: &'a [u8]) -> BoundaryBuffer {
+        match BoundaryBuffer::new(bytes) {
+            Ok(buffer) => buffer,
+            Err(err) => panic!("page_size: {}", err),
+        }
+    }
+}
+
+impl Deref for BoundaryBuffer {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        &self.mapping[self.start..self.start + self.len]
+    }
+}
+
+impl DerefMut for BoundaryBuffer {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.mapping[self.start..self.start + self.len]
+    }
+}
+
+impl AsRef<[u8]> for BoundaryBuffer {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl AsMut<[u8]> for BoundaryBuffer {
+    #[inline]
+    fn as_mut(&mut self) -> &mut [u8] {
+        self
+    }
+}
+
+impl fmt::Debug for BoundaryBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BoundaryBuffer")
+            .field("ptr", &self.as_ptr())
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_boundary_buffer() {
+        let page_size = ::get();
+
+        let buffer = BoundaryBuffer::from(&b"abc"[..]);
+        assert_eq!(&*buffer, b"abc");
+        assert_eq!((buffer.as_ptr() as usize + 3) % page_size, 0);
+        assert!(buffer.mapping.guard_after() && !buffer.mapping.guard_before());
+
+        let mut buffer = BoundaryBuffer::underflow(b"abc").unwrap();
+        buffer[0] = b'x';
+        assert_eq!(&*buffer, b"xbc");
+        assert_eq!(buffer.as_ptr() as usize % page_size, 0);
+        assert!(buffer.mapping.guard_before());
+
+        let buffer = BoundaryBuffer::new(&[]).unwrap();
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.as_ptr() as usize % page_size, 0);
+    }
+}
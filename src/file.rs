@@ -0,0 +1,412 @@
+//! The page cache state of files, and controlling it.
+
+#[cfg(any(not(feature = "no_std"), feature = "alloc"))]
+use alloc::vec::Vec;
+use core::ops::Range;
+use core::ptr;
+
+use residency::for_each_page_residency;
+#[cfg(any(not(feature = "no_std"), feature = "alloc"))]
+use residency::Residency;
+use unix::sys;
+use {Align, Error, PageSize};
+
+// Files are mapped this many bytes at a time, so that ranges larger than the
+// address space can be inspected. A 32-bit address space has little room for
+// large mappings.
+const WINDOW: u64 = if usize::BITS < 64 { 1 << 26 } else { 1 << 30 };
+
+// The largest offset in a file: the kernel's `loff_t` is signed.
+const MAX_OFFSET: u64 = i64::MAX as u64;
+
+/// Calls `f` with the residency of each page of the file `fd` overlapping the
+/// byte range `range`, in order: `true` if the page is in the page cache.
+///
+/// `range` is rounded outward to page boundaries and, like `fincore`, ends at
+/// the end of the file, so `range.end` may be past it. `fd` must be open for
+/// reading; it is mapped to query the page cache with `mincore`, and is not
+/// closed.
+///
+/// The kernel only reports the page cache state of files the process owns
+/// or could open for writing. For other files, only the pages the process
+/// itself has mapped are reported as resident.
+///
+/// This is the allocation-free version of `file_residency`.
+pub fn for_each_file_page_residency<F>(fd: i32, range: Range<u64>, mut f: F) -> Result<(), Error>
+where
+    F: FnMut(bool),
+{
+    let size = sys::file_size(fd).map_err(|errno| Error::Os {
+        operation: "fstat",
+        errno,
+    })?;
+    let range = file_page_range(range, size)?;
+
+    let mut offset = range.start;
+    while offset < range.end {
+        let len = (range.end - offset).min(WINDOW) as usize;
+        let ptr = unsafe {
+            sys::mmap(
+                ptr::null_mut(),
+                len,
+                sys::PROT_READ,
+                sys::MAP_SHARED,
+                fd,
+                offset,
+            )
+        }
+        .map_err(|errno| Error::Os {
+            operation: "mmap",
+            errno,
+        })?;
+
+        let result = for_each_page_residency(ptr as usize..ptr as usize + len, &mut f);
+        unsafe { sys::munmap(ptr, len) }.map_err(|errno| Error::Os {
+            operation: "munmap",
+            errno,
+        })?;
+        result?;
+
+        offset += len as u64;
+    }
+
+    Ok(())
+}
+
+/// Returns which pages of the file `fd` overlapping the byte range `range`
+/// are in the page cache, like `fincore` does.
+///
+/// Page indices in the result count from the page containing
+/// `range.start`. See [`for_each_file_page_residency`] for details.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate page_size;
+/// use std::fs::File;
+/// use std::os::unix::io::AsRawFd;
+///
+/// let file = File::open("/proc/self/exe").unwrap();
+/// let len = file.metadata().unwrap().len();
+/// let residency = page_size::file_residency(file.as_raw_fd(), 0..len).unwrap();
+/// println!("{} of {} pages cached", residency.resident_pages(), residency.pages());
+/// ```
+#[cfg(any(not(feature = "no_std"), feature = "alloc"))]
+pub fn file_residency(fd: i32, range: Range<u64>) -> Result<Residency, Error> {
+    let mut pages = Vec::new();
+    for_each_file_page_residency(fd, range, |resident| pages.push(resident))?;
+
+    Ok(Residency::new(pages))
+}
+
+/// Advice about the use of a file, given with [`fadvise`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum FileAdvice {
+    /// `POSIX_FADV_NORMAL`: undo [`Sequential`](FileAdvice::Sequential) and
+    /// [`Random`](FileAdvice::Random).
+    Normal,
+    /// `POSIX_FADV_SEQUENTIAL`: the file will be read sequentially, so read
+    /// ahead more aggressively.
+    Sequential,
+    /// `POSIX_FADV_RANDOM`: the file will be read randomly, so do not read
+    /// ahead.
+    Random,
+    /// `POSIX_FADV_WILLNEED`: read the range into the page cache in the
+    /// background.
+    WillNeed,
+    /// `POSIX_FADV_DONTNEED`: evict the range from the page cache.
+    DontNeed,
+}
+
+impl FileAdvice {
+    fn code(self) -> i32 {
+        match self {
+            FileAdvice::Normal => sys::POSIX_FADV_NORMAL,
+            FileAdvice::Sequential => sys::POSIX_FADV_SEQUENTIAL,
+            FileAdvice::Random => sys::POSIX_FADV_RANDOM,
+            FileAdvice::WillNeed => sys::POSIX_FADV_WILLNEED,
+            FileAdvice::DontNeed => sys::POSIX_FADV_DONTNEED,
+        }
+    }
+
+    fn operation(self) -> &'static str {
+        match self {
+            FileAdvice::Normal => "posix_fadvise(POSIX_FADV_NORMAL)",
+            FileAdvice::Sequential => "posix_fadvise(POSIX_FADV_SEQUENTIAL)",
+            FileAdvice::Random => "posix_fadvise(POSIX_FADV_RANDOM)",
+            FileAdvice::WillNeed => "posix_fadvise(POSIX_FADV_WILLNEED)",
+            FileAdvice::DontNeed => "posix_fadvise(POSIX_FADV_DONTNEED)",
+        }
+    }
+}
+
+/// Gives the kernel `advice` about the byte range `range` of the file `fd`.
+///
+/// A `range.end` past the largest file offset (`i64::MAX`), such as
+/// `u64::MAX`, stands for the end of the file. The range is rounded to page
+/// boundaries the way that makes the advice exact:
+///
+/// - For [`FileAdvice::WillNeed`], outward, so that every byte of `range`
+///   gets read.
+/// - For [`FileAdvice::DontNeed`], inward, so that pages holding bytes
+///   outside `range` stay cached. The last page of the file, if partial, is
+///   only evicted when `range` extends to the end of the file. Dirty pages
+///   are not evicted until they have been written back.
+/// - [`FileAdvice::Normal`], [`FileAdvice::Sequential`] and
+///   [`FileAdvice::Random`] apply to the whole open file on Linux, so
+///   `range` is ignored.
+///
+/// Fails with an [`Error::OutOfRange`] if `range` is reversed, and with an
+/// [`Error::Os`] if the kernel refuses the advice, for example because `fd`
+/// is a pipe (`ESPIPE`). Reversed ranges are accepted for the advice that
+/// ignores them.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate page_size;
+/// use page_size::FileAdvice;
+/// use std::fs::File;
+/// use std::os::unix::io::AsRawFd;
+///
+/// // Start from a cold cache.
+/// let file = File::open("/proc/self/exe").unwrap();
+/// page_size::fadvise(file.as_raw_fd(), 0..u64::MAX, FileAdvice::DontNeed).unwrap();
+/// ```
+pub fn fadvise(fd: i32, range: Range<u64>, advice: FileAdvice) -> Result<(), Error> {
+    let pages = match advice {
+        FileAdvice::WillNeed => advice_range(range, false)?,
+        FileAdvice::DontNeed => advice_range(range, true)?,
+        _ => Some((0, 0)),
+    };
+    let (offset, len) = match pages {
+        Some(pages) => pages,
+        None => return Ok(()),
+    };
+
+    sys::fadvise(fd, offset, len, advice.code()).map_err(|errno| Error::Os {
+        operation: advice.operation(),
+        errno,
+    })
+}
+
+/// Reads the byte range `range` of the file `fd` into the page cache,
+/// rounded outward to page boundaries. Returns once the reads have been
+/// issued.
+///
+/// A `range.end` past the largest file offset (`i64::MAX`), such as
+/// `u64::MAX`, stands for the end of the file. Fails with an
+/// [`Error::OutOfRange`] if `range` is reversed, and with an [`Error::Os`] if
+/// `fd` does not support read-ahead (`EINVAL`).
+pub fn readahead(fd: i32, range: Range<u64>) -> Result<(), Error> {
+    let (offset, len) = match advice_range(range, false)? {
+        Some(pages) => pages,
+        None => return Ok(()),
+    };
+    // Unlike `posix_fadvise`, `readahead` has no length meaning "to the end
+    // of the file", but stops there anyway. The kernel refuses ranges that
+    // extend past the largest file offset.
+    let count = match len {
+        0 => MAX_OFFSET - offset,
+        len => len,
+    }
+    .min(usize::MAX as u64) as usize;
+
+    sys::readahead(fd, offset, count).map_err(|errno| Error::Os {
+        operation: "readahead",
+        errno,
+    })
+}
+
+/// Rounds the file byte range `range` to page boundaries, outward or
+/// `inward`, returning the offset and length to pass to `posix_fadvise`
+/// (where a length of 0 means "to the end of the file"), or `None` if no
+/// page is left. Offsets past [`MAX_OFFSET`] are beyond the end of any file.
+fn advice_range(range: Range<u64>, inward: bool) -> Result<Option<(u64, u64)>, Error> {
+    let page_size = PageSize::get();
+    if range.start > range.end {
+        return Err(out_of_range(range));
+    }
+    if range.start == range.end {
+        return Ok(None);
+    }
+
+    let start = if inward {
+        match range.start.page_align_up(page_size) {
+            Some(start) => start,
+            None => return Ok(None),
+        }
+    } else {
+        range.start.page_align_down(page_size)
+    };
+    if start > MAX_OFFSET {
+        return Ok(None);
+    }
+
+    let end = if inward {
+        range.end.page_align_down(page_size)
+    } else {
+        range.end.page_align_up(page_size).unwrap_or(u64::MAX)
+    };
+    if end > MAX_OFFSET {
+        return Ok(Some((start, 0)));
+    }
+    if start >= end {
+        return Ok(None);
+    }
+
+    Ok(Some((start, end - start)))
+}
+
+/// Rounds the file byte range `range` outward to page boundaries, with the
+/// end clamped to a file of `size` bytes.
+fn file_page_range(range: Range<u64>, size: u64) -> Result<Range<u64>, Error> {
+    let page_size = PageSize::get();
+    match range.end.min(size).page_align_up(page_size) {
+        Some(end) if range.start <= range.end => {
+            let start = range.start.page_align_down(page_size);
+            Ok(start..end.max(start))
+        }
+        _ => Err(out_of_range(range)),
+    }
+}
+
+/// Reports the file byte range `range` as an [`Error::OutOfRange`], with
+/// offsets past the address space clamped to `usize::MAX`.
+fn out_of_range(range: Range<u64>) -> Error {
+    let clamp = |offset: u64| offset.min(usize::MAX as u64) as usize;
+    Error::OutOfRange {
+        start: clamp(range.start),
+        end: clamp(range.end),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_page_range() {
+        let page_size = ::get() as u64;
+        let size = 3 * page_size + 1;
+        assert_eq!(
+            file_page_range(1..page_size + 1, size),
+            Ok(0..2 * page_size)
+        );
+        assert_eq!(
+            file_page_range(page_size..page_size, size),
+            Ok(page_size..page_size)
+        );
+        assert_eq!(file_page_range(0..u64::MAX, size), Ok(0..4 * page_size));
+        assert_eq!(
+            file_page_range(5 * page_size + 1..u64::MAX, size),
+            Ok(5 * page_size..5 * page_size)
+        );
+        assert!(file_page_range(page_size..1, size).is_err());
+    }
+
+    #[test]
+    fn test_out_of_range() {
+        assert_eq!(
+            out_of_range(5..u64::MAX),
+            Error::OutOfRange {
+                start: 5,
+                end: usize::MAX
+            }
+        );
+        #[cfg(target_pointer_width = "32")]
+        assert_eq!(
+            out_of_range(1 << 32..1),
+            Error::OutOfRange {
+                start: usize::MAX,
+                end: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_advice_range() {
+        let page_size = ::get() as u64;
+        let outward = |range| advice_range(range, false).unwrap();
+        let inward = |range| advice_range(range, true).unwrap();
+
+        assert_eq!(outward(1..page_size + 1), Some((0, 2 * page_size)));
+        assert_eq!(inward(1..3 * page_size - 1), Some((page_size, page_size)));
+        assert_eq!(inward(1..page_size + 1), None);
+        assert_eq!(outward(5..5), None);
+        assert_eq!(inward(1..u64::MAX), Some((page_size, 0)));
+        assert_eq!(outward(1..u64::MAX), Some((0, 0)));
+        assert_eq!(outward(1..u64::MAX - 1), Some((0, 0)));
+        assert_eq!(outward(1..MAX_OFFSET), Some((0, 0)));
+        assert_eq!(
+            inward(1..MAX_OFFSET),
+            Some((page_size, (MAX_OFFSET & !(page_size - 1)) - page_size))
+        );
+        assert_eq!(outward(MAX_OFFSET + 1..u64::MAX), None);
+        assert!(advice_range(page_size..1, false).is_err());
+    }
+
+    #[test]
+    fn test_fadvise() {
+        let page_size = ::get() as u64;
+        let fd = sys::open(b"/proc/self/exe\0").unwrap();
+
+        let results = [
+            fadvise(fd, 0..u64::MAX, FileAdvice::Sequential),
+            fadvise(fd, 0..page_size, FileAdvice::WillNeed),
+            readahead(fd, 1..2 * page_size),
+            fadvise(fd, 1..2, FileAdvice::DontNeed),
+            fadvise(fd, 0..u64::MAX - 1, FileAdvice::WillNeed),
+            fadvise(fd, page_size..MAX_OFFSET + 1, FileAdvice::DontNeed),
+            readahead(fd, 0..u64::MAX),
+            readahead(fd, page_size..MAX_OFFSET),
+            fadvise(fd, 0..u64::MAX, FileAdvice::Normal),
+        ];
+        sys::close(fd);
+        for result in &results {
+            assert_eq!(*result, Ok(()));
+        }
+
+        assert_eq!(
+            readahead(-1, 0..1),
+            Err(Error::Os {
+                operation: "readahead",
+                errno: sys::EBADF
+            })
+        );
+    }
+
+    #[test]
+    fn test_for_each_file_page_residency() {
+        let page_size = ::get() as u64;
+        // The test binary is being executed, so its first page is cached.
+        let fd = sys::open(b"/proc/self/exe\0").unwrap();
+
+        let mut pages = [false; 4];
+        let mut count = 0;
+        let result = for_each_file_page_residency(fd, 0..4 * page_size - 1, |resident| {
+            pages[count] = resident;
+            count += 1;
+        });
+        result.unwrap();
+        assert_eq!(count, 4);
+        assert!(pages[0]);
+
+        // Pages past the end of the file are not reported.
+        let size = sys::file_size(fd).unwrap();
+        let mut count = 0;
+        let result = for_each_file_page_residency(fd, 0..u64::MAX, |_| count += 1);
+        sys::close(fd);
+        result.unwrap();
+        assert_eq!(count as u64, size.div_ceil(page_size));
+
+        assert_eq!(
+            for_each_file_page_residency(-1, 0..1, |_| {}),
+            Err(Error::Os {
+                operation: "fstat",
+                errno: sys::EBADF
+            })
+        );
+    }
+}
@@ -0,0 +1,302 @@
+//! Reading procfs and sysfs files without the standard library.
+//!
+//! The files this crate reads are small and textual, so everything here works
+//! on fixed-size buffers and goes through the same `open`/`read` primitives as
+//! the page size query, which keeps it available in `no_std` and `linux-raw`
+//! builds.
+
+use unix::sys;
+use Error;
+
+// Long enough for any sysfs path this crate builds, plus a directory entry
+// name of up to 255 bytes.
+const PATH_MAX: usize = 384;
+
+/// A NUL-terminated path built in a fixed-size buffer.
+#[derive(Clone)]
+pub struct Path {
+    buf: [u8; PATH_MAX],
+    len: usize,
+}
+
+impl Path {
+    pub fn new(path: &str) -> Path {
+        let mut buf = Path {
+            buf: [0; PATH_MAX],
+            len: 0,
+        };
+        buf.push(path.as_bytes());
+        buf
+    }
+
+    /// Appends `bytes`, truncating the path if it gets too long (which makes
+    /// opening it fail).
+    pub fn push(&mut self, bytes: &[u8]) -> &mut Path {
+        // Keep the last byte for the terminating NUL.
+        let count = bytes.len().min(PATH_MAX - 1 - self.len);
+        self.buf[self.len..self.len + count].copy_from_slice(&bytes[..count]);
+        self.len += count;
+        self
+    }
+
+    /// Appends `value` in decimal.
+    pub fn push_usize(&mut self, mut value: usize) -> &mut Path {
+        let mut digits = [0u8; 20];
+        let mut start = digits.len();
+        loop {
+            start -= 1;
+            digits[start] = b'0' + (value % 10) as u8;
+            value /= 10;
+            if value == 0 {
+                break;
+            }
+        }
+        self.push(&digits[start..])
+    }
+
+    /// Returns a copy of the path with `name` appended.
+    pub fn join(&self, name: &[u8]) -> Path {
+        let mut path = self.clone();
+        path.push(name);
+        path
+    }
+
+    fn as_c_str(&self) -> &[u8] {
+        &self.buf[..self.len + 1]
+    }
+}
+
+/// A file opened for reading, closed on drop.
+pub struct File {
+    fd: i32,
+}
+
+impl File {
+    pub fn open(path: &Path) -> Result<File, Error> {
+        sys::open(path.as_c_str())
+            .map(|fd| File { fd })
+            .map_err(|errno| Error::Os {
+                operation: "open",
+                errno,
+            })
+    }
+
+    /// Reads until `buf` is full or the end of the file is reached.
+    pub fn read_full(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        sys::read_full(self.fd, buf).map_err(|errno| Error::Os {
+            operation: "read",
+            errno,
+        })
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        sys::close(self.fd);
+    }
+}
+
+/// Reads the file at `path` into `buf`, returning the part of `buf` that was
+/// filled. Longer files are truncated.
+pub fn read<'a>(path: &Path, buf: &'a mut [u8]) -> Result<&'a [u8], Error> {
+    let count = File::open(path)?.read_full(buf)?;
+    Ok(&buf[..count])
+}
+
+/// Reads a file holding a single decimal number, such as most sysfs
+/// attributes.
+pub fn read_usize(path: &Path, what: &'static str) -> Result<usize, Error> {
+    let mut buf = [0u8; 32];
+    let contents = read(path, &mut buf)?;
+    parse_usize(contents).ok_or(Error::Malformed { what })
+}
+
+/// Calls `f` with each line of the file at `path`, without the trailing
+/// newline, until `f` returns `false`.
+///
+/// Lines longer than the internal buffer are cut short.
+pub fn for_each_line<F>(path: &Path, mut f: F) -> Result<(), Error>
+where
+    F: FnMut(&[u8]) -> bool,
+{
+    let mut file = File::open(path)?;
+    let mut buf = [0u8; 4096];
+    let (mut start, mut end) = (0, 0);
+    // Set while skipping the rest of a line that did not fit in `buf`.
+    let mut skipping = false;
+
+    loop {
+        buf.copy_within(start..end, 0);
+        end -= start;
+        start = 0;
+
+        let count = file.read_full(&mut buf[end..])?;
+        end += count;
+
+        while let Some(newline) = buf[start..end].iter().position(|&b| b == b'\n') {
+            if !skipping && !f(&buf[start..start + newline]) {
+                return Ok(());
+            }
+            skipping = false;
+            start += newline + 1;
+        }
+
+        if count == 0 {
+            if start < end && !skipping {
+                f(&buf[start..end]);
+            }
+            return Ok(());
+        }
+
+        if start == 0 && end == buf.len() {
+            if !skipping && !f(&buf) {
+                return Ok(());
+            }
+            skipping = true;
+            start = end;
+        }
+    }
+}
+
+/// Calls `f` with the name of each entry of the directory at `path`.
+pub fn for_each_entry<F>(path: &Path, mut f: F) -> Result<(), Error>
+where
+    F: FnMut(&[u8]),
+{
+    // Offsets into a `struct linux_dirent64`.
+    const RECLEN: usize = 16;
+    const NAME: usize = 19;
+
+    let dir = File::open(path)?;
+    let mut buf = [0u8; 4096];
+    loop {
+        let count = sys::getdents64(dir.fd, &mut buf).map_err(|errno| Error::Os {
+            operation: "getdents64",
+            errno,
+        })?;
+        if count == 0 {
+            return Ok(());
+        }
+
+        let mut offset = 0;
+        while offset < count {
+            let record = &buf[offset..count];
+            let reclen = u16::from_ne_bytes([record[RECLEN], record[RECLEN + 1]]) as usize;
+            let name = &record[NAME..reclen];
+            let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+            if name != b"." && name != b".." {
+                f(name);
+            }
+            offset += reclen;
+        }
+    }
+}
+
+/// Parses a decimal number surrounded by optional ASCII whitespace.
+pub fn parse_usize(bytes: &[u8]) -> Option<usize> {
+    let digits = trim(bytes);
+    if digits.is_empty() {
+        return None;
+    }
+
+    digits.iter().try_fold(0usize, |value, &digit| {
+        if digit.is_ascii_digit() {
+            value.checked_mul(10)?.checked_add((digit - b'0') as usize)
+        } else {
+            None
+        }
+    })
+}
+
+/// Parses a hexadecimal number surrounded by optional ASCII whitespace.
+pub fn parse_hex(bytes: &[u8]) -> Option<u64> {
+    let digits = trim(bytes);
+    if digits.is_empty() {
+        return None;
+    }
+
+    digits.iter().try_fold(0u64, |value, &digit| {
+        let digit = (digit as char).to_digit(16)? as u64;
+        value.checked_mul(16)?.checked_add(digit)
+    })
+}
+
+/// Strips leading and trailing ASCII whitespace.
+pub fn trim(bytes: &[u8]) -> &[u8] {
+    let start = bytes
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(bytes.len());
+    let end = bytes
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .map_or(start, |last| last + 1);
+    &bytes[start..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path() {
+        let mut path = Path::new("/proc/");
+        path.push_usize(0).push(b"/").push_usize(1234);
+        assert_eq!(path.as_c_str(), b"/proc/0/1234\0");
+        assert_eq!(path.join(b"/maps").as_c_str(), b"/proc/0/1234/maps\0");
+    }
+
+    #[test]
+    fn test_parse_usize() {
+        assert_eq!(parse_usize(b" 2048\n"), Some(2048));
+        assert_eq!(parse_usize(b"0"), Some(0));
+        assert_eq!(parse_usize(b""), None);
+        assert_eq!(parse_usize(b"12 kB"), None);
+        assert_eq!(parse_usize(b"99999999999999999999999"), None);
+    }
+
+    #[test]
+    fn test_parse_hex() {
+        assert_eq!(parse_hex(b"\t000001fffeffffff\n"), Some(0x1fffeffffff));
+        assert_eq!(parse_hex(b"7ffe78d40000"), Some(0x7ffe78d40000));
+        assert_eq!(parse_hex(b""), None);
+        assert_eq!(parse_hex(b"xyz"), None);
+        assert_eq!(parse_hex(b"10000000000000000"), None);
+    }
+
+    #[test]
+    fn test_for_each_line() {
+        let mut lines = 0;
+        let mut found = false;
+        for_each_line(&Path::new("/proc/self/status"), |line| {
+            lines += 1;
+            found |= line.starts_with(b"Name:");
+            true
+        })
+        .unwrap();
+        assert!(lines > 1 && found);
+
+        let mut first = 0;
+        for_each_line(&Path::new("/proc/self/status"), |_| {
+            first += 1;
+            false
+        })
+        .unwrap();
+        assert_eq!(first, 1);
+    }
+
+    #[test]
+    fn test_for_each_entry() {
+        let mut found = false;
+        for_each_entry(&Path::new("/proc/self"), |name| found |= name == b"status").unwrap();
+        assert!(found);
+
+        assert_eq!(
+            for_each_entry(&Path::new("/nonexistent"), |_| {}).err(),
+            Some(Error::Os {
+                operation: "open",
+                errno: sys::ENOENT
+            })
+        );
+    }
+}
@@ -0,0 +1,218 @@
+//! Memory mappings surrounded by inaccessible guard pages.
+
+use core::ops::{Deref, DerefMut};
+use core::ptr::{self, NonNull};
+use core::{fmt, slice};
+
+use unix::sys;
+use {Error, PageSize};
+
+/// A private, anonymous, read-write memory mapping of whole pages, with an
+/// inaccessible guard page before and/or after it, unmapped on drop.
+///
+/// Any access to a guard page faults, so running off either end of the
+/// mapping raises `SIGSEGV` instead of silently corrupting neighbouring
+/// memory. This suits stacks, buffers and arenas that are written to by
+/// untrusted or unchecked code.
+///
+/// The memory is zero-filled and page-aligned. It dereferences to `[u8]`,
+/// which covers the usable pages but not the guard pages.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate page_size;
+/// use page_size::GuardedMapping;
+///
+/// let mut stack = GuardedMapping::new(64 * 1024, true, false).unwrap();
+/// assert_eq!(stack.len() % page_size::get(), 0);
+/// // Writing below `stack.as_ptr()` would fault.
+/// stack[0] = 1;
+/// ```
+pub struct GuardedMapping {
+    ptr: NonNull<u8>,
+    len: usize,
+    guard_before: bool,
+    guard_after: bool,
+}
+
+// A `GuardedMapping` owns its memory like a `Box<[u8]>` does.
+unsafe impl Send for GuardedMapping {}
+unsafe impl Sync for GuardedMapping {}
+
+impl GuardedMapping {
+    /// Maps at least `len` bytes, rounded up to a whole number of pages of
+    /// the system's page size, preceded by a guard page if `guard_before` is
+    /// set and followed by one if `guard_after` is set.
+    ///
+    /// Fails with an [`Error::Os`] if `len` is 0 or the kernel refuses the
+    /// mapping.
+    pub fn new(len: usize, guard_before: bool, guard_after: bool) -> Result<GuardedMapping, Error> {
+        let page_size = PageSize::get();
+        if len == 0 {
+            return Err(Error::Os {
+                operation: "mmap",
+                errno: sys::EINVAL,
+            });
+        }
+        let before = if guard_before { page_size.bytes() } else { 0 };
+        let after = if guard_after { page_size.bytes() } else { 0 };
+        let too_large = Error::Os {
+            operation: "mmap",
+            errno: sys::ENOMEM,
+        };
+        let len = page_size
+            .checked_mul(page_size.pages_needed(len))
+            .ok_or(too_large)?;
+        let total = len.checked_add(before + after).ok_or(too_large)?;
+
+        // Map everything inaccessible, then open up the usable pages, so the
+        // guard pages are never charged as committed memory.
+        let base = unsafe {
+            sys::mmap(
+                ptr::null_mut(),
+                total,
+                sys::PROT_NONE,
+                sys::MAP_PRIVATE | sys::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        }
+        .map_err(|errno| Error::Os {
+            operation: "mmap",
+            errno,
+        })?;
+        let ptr = unsafe { base.add(before) };
+        if let Err(errno) = unsafe { sys::mprotect(ptr, len, sys::PROT_READ | sys::PROT_WRITE) } {
+            unsafe { sys::munmap(base, total) }.ok();
+            return Err(Error::Os {
+                operation: "mprotect",
+                errno,
+            });
+        }
+
+        Ok(GuardedMapping {
+            ptr: unsafe { NonNull::new_unchecked(ptr) },
+            len,
+            guard_before,
+            guard_after,
+        })
+    }
+
+    /// Returns `true` if the mapping is preceded by a guard page.
+    #[inline]
+    pub fn guard_before(&self) -> bool {
+        self.guard_before
+    }
+
+    /// Returns `true` if the mapping is followed by a guard page.
+    #[inline]
+    pub fn guard_after(&self) -> bool {
+        self.guard_after
+    }
+
+    // Returns the start and length of the whole mapping, guard pages
+    // included.
+    fn mapped(&self) -> (*mut u8, usize) {
+        let page_size = ::get();
+        let before = if self.guard_before { page_size } else { 0 };
+        let after = if self.guard_after { page_size } else { 0 };
+        unsafe { (self.ptr.as_ptr().sub(before), self.len + before + after) }
+    }
+}
+
+impl Deref for GuardedMapping {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for GuardedMapping {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl AsRef<[u8]> for GuardedMapping {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl AsMut<[u8]> for GuardedMapping {
+    #[inline]
+    fn as_mut(&mut self) -> &mut [u8] {
+        self
+    }
+}
+
+impl fmt::Debug for GuardedMapping {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GuardedMapping")
+            .field("ptr", &self.ptr)
+            .field("len", &self.len)
+            .field("guard_before", &self.guard_before)
+            .field("guard_after", &self.guard_after)
+            .finish()
+    }
+}
+
+impl Drop for GuardedMapping {
+    fn drop(&mut self) {
+        let (base, total) = self.mapped();
+        let result = unsafe { sys::munmap(base, total) };
+        debug_assert!(result.is_ok(), "munmap failed: {:?}", result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guarded_mapping() {
+        let page_size = ::get();
+
+        let mut mapping = GuardedMapping::new(page_size + 1, true, true).unwrap();
+        assert_eq!(mapping.len(), 2 * page_size);
+        assert_eq!(mapping.as_ptr() as usize % page_size, 0);
+        assert!(mapping.iter().all(|&byte| byte == 0));
+        for byte in mapping.iter_mut() {
+            *byte = 0xa5;
+        }
+
+        // The guard pages are mapped, but not accessible.
+        let (base, total) = mapping.mapped();
+        assert_eq!(total, 4 * page_size);
+        let start = base as usize;
+        let mut pages = [true; 4];
+        let mut count = 0;
+        ::for_each_page_residency(start..start + total, |resident| {
+            pages[count] = resident;
+            count += 1;
+        })
+        .unwrap();
+        assert_eq!(pages, [false, true, true, false]);
+
+        let mapping = GuardedMapping::new(1, false, true).unwrap();
+        assert_eq!(
+            mapping.mapped(),
+            (mapping.as_ptr() as *mut u8, 2 * page_size)
+        );
+        assert!(!mapping.guard_before() && mapping.guard_after());
+
+        assert!(GuardedMapping::new(0, true, true).is_err());
+        assert_eq!(
+            GuardedMapping::new(usize::MAX, true, true).err(),
+            Some(Error::Os {
+                operation: "mmap",
+                errno: sys::ENOMEM
+            })
+        );
+    }
+}
@@ -0,0 +1,366 @@
+//! Huge page sizes supported by the Linux kernel.
+
+#[cfg(any(not(feature = "no_std"), feature = "alloc"))]
+use alloc::vec::Vec;
+
+use fs::{self, Path};
+use unix::sys;
+use {Error, PageSize};
+
+/// The directory with one `hugepages-<size>kB` subdirectory per huge page
+/// size supported by hugetlbfs.
+pub const HUGEPAGES_DIR: &str = "/sys/kernel/mm/hugepages";
+
+/// The directory with one `node<N>` subdirectory per NUMA node.
+const NODE_DIR: &str = "/sys/devices/system/node";
+
+/// Returns every huge page size supported by the kernel, in increasing order.
+///
+/// The sizes are those of the `hugepages-<size>kB` directories under
+/// `/sys/kernel/mm/hugepages`. The list is empty if the kernel was built
+/// without hugetlbfs support.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate page_size;
+/// for size in page_size::huge_page_sizes().unwrap() {
+///     println!("{} KiB", size.bytes() / 1024);
+/// }
+/// ```
+#[cfg(any(not(feature = "no_std"), feature = "alloc"))]
+pub fn huge_page_sizes() -> Result<Vec<PageSize>, Error> {
+    let mut sizes = Vec::new();
+    for_each_huge_page_size(|size| sizes.push(size))?;
+    sizes.sort();
+
+    Ok(sizes)
+}
+
+/// Calls `f` with every huge page size supported by the kernel, in no
+/// particular order.
+///
+/// This is the allocation-free version of `huge_page_sizes`.
+pub fn for_each_huge_page_size<F>(mut f: F) -> Result<(), Error>
+where
+    F: FnMut(PageSize),
+{
+    for_each_size_dir(&Path::new(HUGEPAGES_DIR), |size, _| f(size))
+}
+
+/// Returns the default huge page size, i.e. the size of the pages mapped with
+/// `MAP_HUGETLB` when no explicit size is requested.
+///
+/// This is the `Hugepagesize` field of `/proc/meminfo`. It is `None` if the
+/// kernel was built without hugetlbfs support.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate page_size;
+/// if let Some(size) = page_size::default_huge_page_size().unwrap() {
+///     println!("{} KiB", size.bytes() / 1024);
+/// }
+/// ```
+pub fn default_huge_page_size() -> Result<Option<PageSize>, Error> {
+    let mut result = Ok(None);
+    fs::for_each_line(&Path::new("/proc/meminfo"), |line| {
+        if !line.starts_with(b"Hugepagesize:") {
+            return true;
+        }
+        result = parse_kib(&line[b"Hugepagesize:".len()..])
+            .and_then(PageSize::new)
+            .map(Some)
+            .ok_or(Error::Malformed {
+                what: "Hugepagesize in /proc/meminfo",
+            });
+        false
+    })?;
+
+    result
+}
+
+/// The state of a hugetlb pool: the huge pages of one size, either system-wide
+/// or on a single NUMA node.
+///
+/// The fields mirror the files of the pool's sysfs directory,
+/// `/sys/kernel/mm/hugepages/hugepages-<size>kB` for the system-wide pool and
+/// `/sys/devices/system/node/node<N>/hugepages/hugepages-<size>kB` for a
+/// node's pool. Counts are in huge pages.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate page_size;
+/// use page_size::HugePagePool;
+///
+/// // Back a 64 MiB arena with huge pages only if the pool can hold it.
+/// if let Some(size) = page_size::default_huge_page_size().unwrap() {
+///     let pool = HugePagePool::read(size).unwrap();
+///     println!("hugetlb arena: {}", pool.fits(64 << 20));
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HugePagePool {
+    /// The size of the pages in the pool.
+    pub size: PageSize,
+    /// The NUMA node of the pool, or `None` for the system-wide pool.
+    pub node: Option<usize>,
+    /// The number of huge pages in the pool, surplus pages included.
+    pub nr_hugepages: usize,
+    /// The number of huge pages not yet faulted in. This includes the
+    /// reserved pages.
+    pub free_hugepages: usize,
+    /// The number of free huge pages already promised to existing mappings.
+    /// Only reported for the system-wide pool.
+    pub resv_hugepages: Option<usize>,
+    /// The number of huge pages allocated beyond `nr_hugepages` through
+    /// overcommit.
+    pub surplus_hugepages: usize,
+    /// The maximum number of surplus huge pages. Only reported for the
+    /// system-wide pool.
+    pub nr_overcommit_hugepages: Option<usize>,
+}
+
+impl HugePagePool {
+    /// Reads the system-wide pool of huge pages of `size`.
+    ///
+    /// Fails with an [`Error::Os`] if the kernel does not support huge pages
+    /// of that size.
+    pub fn read(size: PageSize) -> Result<HugePagePool, Error> {
+        read_pool(&Path::new(HUGEPAGES_DIR), size, None)
+    }
+
+    /// Reads the pool of huge pages of `size` on NUMA node `node`.
+    ///
+    /// Fails with an [`Error::Os`] if the node does not exist or the kernel
+    /// does not support huge pages of that size.
+    pub fn read_node(size: PageSize, node: usize) -> Result<HugePagePool, Error> {
+        read_pool(&node_dir(node), size, Some(node))
+    }
+
+    /// Returns the number of huge pages that new mappings are guaranteed to
+    /// get: the free pages that are not reserved by existing mappings.
+    ///
+    /// Surplus pages may be available on top of that, but only if overcommit
+    /// is enabled and the kernel manages to allocate them when needed.
+    pub fn available(&self) -> usize {
+        self.free_hugepages
+            .saturating_sub(self.resv_hugepages.unwrap_or(0))
+    }
+
+    /// Returns `true` if a mapping of `bytes` bytes fits in the
+    /// [`available`](HugePagePool::available) pages.
+    pub fn fits(&self, bytes: usize) -> bool {
+        self.size.pages_needed(bytes) <= self.available()
+    }
+}
+
+/// Calls `f` with the system-wide pool of every huge page size, in no
+/// particular order.
+///
+/// This is the allocation-free version of `huge_page_pools`.
+pub fn for_each_huge_page_pool<F>(mut f: F) -> Result<(), Error>
+where
+    F: FnMut(HugePagePool),
+{
+    let mut result = Ok(());
+    for_each_huge_page_size(|size| {
+        if result.is_ok() {
+            result = HugePagePool::read(size).map(&mut f);
+        }
+    })?;
+
+    result
+}
+
+/// Calls `f` with the pool of every huge page size on every NUMA node, in no
+/// particular order.
+///
+/// This is the allocation-free version of `node_huge_page_pools`. Nothing
+/// is reported if the kernel was built without NUMA support.
+pub fn for_each_node_huge_page_pool<F>(mut f: F) -> Result<(), Error>
+where
+    F: FnMut(HugePagePool),
+{
+    let mut result = Ok(());
+    let listed = fs::for_each_entry(&Path::new(NODE_DIR), |name| {
+        let node = match name.strip_prefix(b"node").and_then(fs::parse_usize) {
+            Some(node) if result.is_ok() => node,
+            _ => return,
+        };
+
+        let dir = node_dir(node);
+        let mut pools = Ok(());
+        let sizes = for_each_size_dir(&dir, |size, _| {
+            if pools.is_ok() {
+                pools = read_pool(&dir, size, Some(node)).map(&mut f);
+            }
+        });
+        result = sizes.and(pools);
+    });
+
+    match listed {
+        Err(Error::Os {
+            errno: sys::ENOENT, ..
+        }) => Ok(()),
+        listed => listed.and(result),
+    }
+}
+
+/// Returns the system-wide pool of every huge page size, by increasing size.
+#[cfg(any(not(feature = "no_std"), feature = "alloc"))]
+pub fn huge_page_pools() -> Result<Vec<HugePagePool>, Error> {
+    let mut pools = Vec::new();
+    for_each_huge_page_pool(|pool| pools.push(pool))?;
+    pools.sort_by_key(|pool| pool.size);
+
+    Ok(pools)
+}
+
+/// Returns the pool of every huge page size on every NUMA node, by node and
+/// then by increasing size.
+#[cfg(any(not(feature = "no_std"), feature = "alloc"))]
+pub fn node_huge_page_pools() -> Result<Vec<HugePagePool>, Error> {
+    let mut pools = Vec::new();
+    for_each_node_huge_page_pool(|pool| pools.push(pool))?;
+    pools.sort_by_key(|pool| (pool.node, pool.size));
+
+    Ok(pools)
+}
+
+/// Calls `f` with the size and directory name of each `hugepages-<size>kB`
+/// entry of `dir`. A missing `dir` has no entries.
+pub fn for_each_size_dir<F>(dir: &Path, mut f: F) -> Result<(), Error>
+where
+    F: FnMut(PageSize, &[u8]),
+{
+    let result = fs::for_each_entry(dir, |name| {
+        if let Some(size) = parse_size_dir(name) {
+            f(size, name);
+        }
+    });
+
+    match result {
+        Err(Error::Os {
+            errno: sys::ENOENT, ..
+        }) => Ok(()),
+        result => result,
+    }
+}
+
+/// Returns the directory holding the `hugepages-<size>kB` pools of `node`.
+fn node_dir(node: usize) -> Path {
+    let mut dir = Path::new(NODE_DIR);
+    dir.push(b"/node").push_usize(node).push(b"/hugepages");
+    dir
+}
+
+/// Reads the pool of huge pages of `size` under `dir`. Per-node pools have no
+/// `resv_hugepages` or `nr_overcommit_hugepages` attributes.
+fn read_pool(dir: &Path, size: PageSize, node: Option<usize>) -> Result<HugePagePool, Error> {
+    let mut dir = dir.clone();
+    dir.push(b"/hugepages-")
+        .push_usize(size.bytes() / 1024)
+        .push(b"kB/");
+    let attr = |name: &'static str| fs::read_usize(&dir.join(name.as_bytes()), name);
+    let global = |name: &'static str| match node {
+        Some(_) => Ok(None),
+        None => attr(name).map(Some),
+    };
+
+    Ok(HugePagePool {
+        size,
+        node,
+        nr_hugepages: attr("nr_hugepages")?,
+        free_hugepages: attr("free_hugepages")?,
+        resv_hugepages: global("resv_hugepages")?,
+        surplus_hugepages: attr("surplus_hugepages")?,
+        nr_overcommit_hugepages: global("nr_overcommit_hugepages")?,
+    })
+}
+
+/// Parses a `hugepages-<size>kB` directory name.
+fn parse_size_dir(name: &[u8]) -> Option<PageSize> {
+    const PREFIX: &[u8] = b"hugepages-";
+    const SUFFIX: &[u8] = b"kB";
+
+    if name.len() < PREFIX.len() + SUFFIX.len()
+        || !name.starts_with(PREFIX)
+        || !name.ends_with(SUFFIX)
+    {
+        return None;
+    }
+
+    let kib = fs::parse_usize(&name[PREFIX.len()..name.len() - SUFFIX.len()])?;
+    kib.checked_mul(1024).and_then(PageSize::new)
+}
+
+/// Parses a `<value> kB` field, as found in `/proc/meminfo` and
+/// `/proc/<pid>/smaps`, into a number of bytes.
+pub fn parse_kib(field: &[u8]) -> Option<usize> {
+    let field = fs::trim(field);
+    if !field.ends_with(b" kB") {
+        return None;
+    }
+
+    fs::parse_usize(&field[..field.len() - 3])?.checked_mul(1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size_dir() {
+        assert_eq!(parse_size_dir(b"hugepages-2048kB"), PageSize::new(2 << 20));
+        assert_eq!(
+            parse_size_dir(b"hugepages-1048576kB"),
+            PageSize::new(1 << 30)
+        );
+        assert_eq!(parse_size_dir(b"hugepages-kB"), None);
+        assert_eq!(parse_size_dir(b"hugepages-3000kB"), None);
+        assert_eq!(parse_size_dir(b"enabled"), None);
+    }
+
+    #[test]
+    fn test_parse_kib() {
+        assert_eq!(parse_kib(b"       2048 kB"), Some(2 << 20));
+        assert_eq!(parse_kib(b"2048"), None);
+    }
+
+    #[cfg(any(not(feature = "no_std"), feature = "alloc"))]
+    #[test]
+    fn test_huge_page_sizes() {
+        let sizes = huge_page_sizes().unwrap();
+        assert!(sizes.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(sizes.iter().all(|&size| size.bytes() > ::get()));
+
+        if let Some(size) = default_huge_page_size().unwrap() {
+            assert!(sizes.contains(&size));
+        }
+    }
+
+    #[cfg(any(not(feature = "no_std"), feature = "alloc"))]
+    #[test]
+    fn test_huge_page_pools() {
+        let pools = huge_page_pools().unwrap();
+        assert_eq!(
+            pools.iter().map(|pool| pool.size).collect::<Vec<_>>(),
+            huge_page_sizes().unwrap()
+        );
+        for pool in &pools {
+            assert_eq!(pool.node, None);
+            assert!(pool.resv_hugepages.is_some() && pool.nr_overcommit_hugepages.is_some());
+            assert!(pool.available() <= pool.free_hugepages);
+            assert!(pool.fits(0));
+        }
+
+        for pool in node_huge_page_pools().unwrap() {
+            assert!(pool.node.is_some());
+            assert_eq!(pool.resv_hugepages, None);
+            let reread = HugePagePool::read_node(pool.size, pool.node.unwrap()).unwrap();
+            assert_eq!((reread.size, reread.node), (pool.size, pool.node));
+        }
+    }
+}
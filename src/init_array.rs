@@ -0,0 +1,66 @@
+//! Eager initialization of the page size cache at program startup.
+//!
+//! With the `init-array` feature, a constructor in the ELF `.init_array`
+//! section queries the page size before `main` runs, and [`get`](::get)
+//! becomes a plain load of the value it found, without a check for an empty
+//! cache.
+//!
+//! If the constructor does not run (e.g. in a program with its own entry
+//! point), or cannot determine the page size, [`get`](::get) returns
+//! [`DEFAULT_PAGE_SIZE`](::DEFAULT_PAGE_SIZE) until one of the fallible
+//! functions, such as [`try_get`](::try_get), has queried it.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use PageSize;
+
+// The page size returned by `get`.
+static BYTES: AtomicUsize = AtomicUsize::new(::DEFAULT_PAGE_SIZE);
+
+#[used]
+#[link_section = ".init_array"]
+static INIT: extern "C" fn(i32, *const *const u8, *const *const u8) = init;
+
+extern "C" fn init(_argc: i32, _argv: *const *const u8, _envp: *const *const u8) {
+    #[cfg(all(feature = "linux-raw", target_env = "gnu"))]
+    unsafe {
+        init_from_envp(_argc, _argv, _envp)
+    };
+
+    // Without `linux-raw`, this reads `AT_PAGESZ` with `getauxval`.
+    let _ = ::try_get();
+}
+
+/// Returns the page size found by the constructor or a later query.
+#[inline]
+pub fn bytes() -> usize {
+    BYTES.load(Ordering::Relaxed)
+}
+
+/// Records a successfully queried page size for [`bytes`].
+pub fn publish(page_size: PageSize) {
+    BYTES.store(page_size.bytes(), Ordering::Relaxed);
+}
+
+// glibc passes `argc`, `argv` and `envp` to `.init_array` functions, but other
+// C libraries (musl in particular) pass nothing, so the arguments can only be
+// trusted on glibc targets.
+//
+// The auxiliary vector follows the environment on the initial stack, so
+// `envp` is only trusted while it still points there, right after `argv`. In
+// a library loaded with `dlopen`, `envp` is the current `environ`, which
+// `setenv` or the program may have replaced. Variables removed in place by
+// `unsetenv` leave extra null entries behind, which read as an empty
+// auxiliary vector.
+#[cfg(all(feature = "linux-raw", target_env = "gnu"))]
+unsafe fn init_from_envp(argc: i32, argv: *const *const u8, envp: *const *const u8) {
+    if argc < 0 || argv.is_null() || envp != argv.add(argc as usize + 1) {
+        return;
+    }
+
+    let mut entry = envp;
+    while !(*entry).is_null() {
+        entry = entry.add(1);
+    }
+    ::init_from_auxv(entry.add(1) as *const usize);
+}
@@ -21,37 +21,504 @@
 //! extern crate page_size;
 //! println!("{}", page_size::get());
 //! ```
+//!
+//! Code that cannot tolerate a bogus value (e.g. memory allocators) should use
+//! the fallible variants instead, which report why the page size could not be
+//! determined.
+//!
+//! ```rust
+//! extern crate page_size;
+//! match page_size::try_get() {
+//!     Ok(page_size) => println!("{}", page_size.bytes()),
+//!     Err(err) => println!("cannot determine page size: {}", err),
+//! }
+//! ```
 
-#[cfg(feature = "no_std")]
-extern crate spin;
-#[cfg(feature = "no_std")]
-use spin::Once;
+use core::fmt;
+use core::num::NonZeroUsize;
+use core::ops::{Div, Mul, Rem};
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 #[cfg(not(feature = "no_std"))]
 extern crate std;
-#[cfg(not(feature = "no_std"))]
-use std::sync::Once;
 
-#[cfg(unix)]
+#[cfg(any(not(feature = "no_std"), feature = "alloc"))]
+extern crate alloc;
+
+#[cfg(all(
+    unix,
+    feature = "libc",
+    not(all(target_os = "linux", feature = "linux-raw"))
+))]
 extern crate libc;
 
+#[cfg(all(
+    unix,
+    not(feature = "libc"),
+    not(all(target_os = "linux", feature = "linux-raw"))
+))]
+compile_error!(
+    "the `libc` feature is required on this target unless `linux-raw` is enabled; \
+     builds without the default features must enable it since 0.7"
+);
+
+#[cfg(all(target_os = "linux", feature = "linux-raw"))]
+mod linux_raw;
+#[cfg(all(target_os = "linux", feature = "linux-raw"))]
+pub use linux_raw::init_from_auxv;
+
 #[cfg(windows)]
 extern crate winapi;
 
+#[cfg(target_os = "linux")]
+mod advise;
+mod align;
+#[cfg(target_os = "linux")]
+mod allocator;
+#[cfg(target_os = "linux")]
+mod boundary;
+#[cfg(target_os = "linux")]
+mod file;
+#[cfg(target_os = "linux")]
+mod fs;
+#[cfg(target_os = "linux")]
+mod guard;
+#[cfg(target_os = "linux")]
+mod huge;
+#[cfg(all(target_os = "linux", feature = "init-array"))]
+mod init_array;
+#[cfg(target_os = "linux")]
+mod lock;
+#[cfg(target_os = "linux")]
+mod mapping;
+#[cfg(target_os = "linux")]
+mod protect;
+#[cfg(target_os = "linux")]
+mod region;
+#[cfg(target_os = "linux")]
+mod residency;
+#[cfg(target_os = "linux")]
+mod smaps;
+mod split;
+#[cfg(target_os = "linux")]
+pub mod thp;
+
+#[cfg(target_os = "linux")]
+pub use advise::{advise, Advice};
+pub use align::{
+    align_down, align_down_granularity, align_up, align_up_granularity, is_aligned,
+    is_aligned_granularity, pages_needed, Align, LayoutExt,
+};
+#[cfg(target_os = "linux")]
+pub use allocator::GuardPageAllocator;
+#[cfg(target_os = "linux")]
+pub use boundary::BoundaryBuffer;
+#[cfg(all(target_os = "linux", any(not(feature = "no_std"), feature = "alloc")))]
+pub use file::file_residency;
+#[cfg(target_os = "linux")]
+pub use file::{fadvise, for_each_file_page_residency, readahead, FileAdvice};
+#[cfg(target_os = "linux")]
+pub use guard::GuardedMapping;
+#[cfg(target_os = "linux")]
+pub use huge::{
+    default_huge_page_size, for_each_huge_page_pool, for_each_huge_page_size,
+    for_each_node_huge_page_pool, HugePagePool,
+};
+#[cfg(all(target_os = "linux", any(not(feature = "no_std"), feature = "alloc")))]
+pub use huge::{huge_page_pools, huge_page_sizes, node_huge_page_pools};
+#[cfg(target_os = "linux")]
+pub use lock::{lock, lock_all, lock_on_fault, unlock, unlock_all, MemlockBudget};
+#[cfg(target_os = "linux")]
+pub use mapping::{map_aligned, Backing, PageMapping};
+#[cfg(target_os = "linux")]
+pub use protect::{protect, Protection};
+#[cfg(target_os = "linux")]
+pub use region::VirtualRegion;
+#[cfg(target_os = "linux")]
+pub use residency::for_each_page_residency;
+#[cfg(all(target_os = "linux", any(not(feature = "no_std"), feature = "alloc")))]
+pub use residency::{residency, Residency, Runs};
+#[cfg(target_os = "linux")]
+pub use smaps::{for_address, MappedPageSize};
+pub use split::{split_pages, PageSplit, Pages, SlicePages, SlicePagesMut, SplitPagesExt};
+
+/// A memory page size: a non-zero power of two number of bytes.
+///
+/// Since the size is a power of two, page arithmetic reduces to shifts and
+/// masks. `PageSize` keeps the shift next to the size, and implements the
+/// division, remainder and multiplication operators with them:
+///
+/// ```rust
+/// extern crate page_size;
+/// let page_size = page_size::PageSize::new(4096).unwrap();
+/// assert_eq!(page_size.shift(), 12);
+/// assert_eq!(page_size.mask(), 0xfff);
+/// assert_eq!(10000usize / page_size, 2);
+/// assert_eq!(10000usize % page_size, 1808);
+/// assert_eq!(3usize * page_size, 12288);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PageSize {
+    bytes: NonZeroUsize,
+    shift: u32,
+}
+
+impl PageSize {
+    /// Creates a `PageSize` from a number of bytes, returning `None` if
+    /// `bytes` is not a non-zero power of two.
+    #[inline]
+    pub fn new(bytes: usize) -> Option<PageSize> {
+        if bytes.is_power_of_two() {
+            NonZeroUsize::new(bytes).map(|bytes| PageSize {
+                bytes,
+                shift: bytes.trailing_zeros(),
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Creates a `PageSize` of `1 << shift` bytes, returning `None` if that
+    /// does not fit in a `usize`.
+    #[inline]
+    pub fn from_shift(shift: u32) -> Option<PageSize> {
+        1usize.checked_shl(shift).and_then(PageSize::new)
+    }
+
+    /// Returns the system's memory page size.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as [`get`].
+    #[inline]
+    pub fn get() -> PageSize {
+        match try_get() {
+            Ok(page_size) => page_size,
+            Err(err) => panic!("page_size: {}", err),
+        }
+    }
+
+    /// Returns the system's memory allocation granularity.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as [`get_granularity`].
+    #[inline]
+    pub fn get_granularity() -> PageSize {
+        match try_get_granularity() {
+            Ok(granularity) => granularity,
+            Err(err) => panic!("page_size: {}", err),
+        }
+    }
+
+    /// Returns the size in bytes.
+    #[inline]
+    pub fn bytes(self) -> usize {
+        self.bytes.get()
+    }
+
+    /// Returns the base 2 logarithm of the size, i.e. `bytes() == 1 << shift()`.
+    #[inline]
+    pub fn shift(self) -> u32 {
+        self.shift
+    }
+
+    /// Returns the mask of the offset bits within a page, i.e. `bytes() - 1`.
+    #[inline]
+    pub fn mask(self) -> usize {
+        self.bytes() - 1
+    }
+
+    /// Returns the number of bytes in `pages` pages, or `None` on overflow.
+    #[inline]
+    pub fn checked_mul(self, pages: usize) -> Option<usize> {
+        if pages > usize::MAX >> self.shift {
+            None
+        } else {
+            Some(pages << self.shift)
+        }
+    }
+
+    #[inline]
+    fn verify(bytes: usize, source: Source) -> Result<PageSize, Error> {
+        PageSize::new(bytes).ok_or(Error::InvalidSize {
+            source,
+            value: bytes,
+        })
+    }
+}
+
+impl From<PageSize> for usize {
+    #[inline]
+    fn from(page_size: PageSize) -> usize {
+        page_size.bytes()
+    }
+}
+
+impl From<PageSize> for NonZeroUsize {
+    #[inline]
+    fn from(page_size: PageSize) -> NonZeroUsize {
+        page_size.bytes
+    }
+}
+
+/// Returns the number of whole pages in `self` bytes.
+#[allow(clippy::suspicious_arithmetic_impl)]
+impl Div<PageSize> for usize {
+    type Output = usize;
+
+    #[inline]
+    fn div(self, page_size: PageSize) -> usize {
+        self >> page_size.shift
+    }
+}
+
+/// Returns the number of whole pages in `self` bytes.
+#[allow(clippy::suspicious_arithmetic_impl)]
+impl Div<PageSize> for u64 {
+    type Output = u64;
+
+    #[inline]
+    fn div(self, page_size: PageSize) -> u64 {
+        self >> page_size.shift
+    }
+}
+
+/// Returns the offset of `self` within its page.
+#[allow(clippy::suspicious_arithmetic_impl)]
+impl Rem<PageSize> for usize {
+    type Output = usize;
+
+    #[inline]
+    fn rem(self, page_size: PageSize) -> usize {
+        self & page_size.mask()
+    }
+}
+
+/// Returns the offset of `self` within its page.
+#[allow(clippy::suspicious_arithmetic_impl)]
+impl Rem<PageSize> for u64 {
+    type Output = u64;
+
+    #[inline]
+    fn rem(self, page_size: PageSize) -> u64 {
+        self & page_size.mask() as u64
+    }
+}
+
+/// Returns the number of bytes in `pages` pages.
+///
+/// # Panics
+///
+/// Panics if the result overflows a `usize`. Use [`PageSize::checked_mul`] to
+/// handle that case instead.
+impl Mul<usize> for PageSize {
+    type Output = usize;
+
+    #[inline]
+    fn mul(self, pages: usize) -> usize {
+        self.checked_mul(pages)
+            .expect("attempt to multiply with overflow")
+    }
+}
+
+/// Returns the number of bytes in `self` pages.
+///
+/// # Panics
+///
+/// Panics if the result overflows a `usize`. Use [`PageSize::checked_mul`] to
+/// handle that case instead.
+impl Mul<PageSize> for usize {
+    type Output = usize;
+
+    #[inline]
+    fn mul(self, page_size: PageSize) -> usize {
+        page_size * self
+    }
+}
+
+impl fmt::Display for PageSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.bytes(), f)
+    }
+}
+
+/// The place a page size (or allocation granularity) was obtained from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Source {
+    /// `getauxval(AT_PAGESZ)` (Linux).
+    Getauxval,
+    /// `sysconf(_SC_PAGESIZE)` (Unix).
+    Sysconf,
+    /// The `AT_PAGESZ` entry of `/proc/self/auxv` (Linux).
+    ProcAuxv,
+    /// The `AT_PAGESZ` entry of the program's initial auxiliary vector, as
+    /// handed to `init_from_auxv` (Linux with the `linux-raw` feature) or
+    /// found by the startup constructor (glibc with the `linux-raw` and
+    /// `init-array` features).
+    InitialAuxv,
+    /// `GetSystemInfo` (Windows).
+    GetSystemInfo,
+    /// A value fixed at compile time for the target.
+    Default,
+}
+
+impl Source {
+    // The (non-zero) code identifying the source in a `Cache`.
+    fn code(self) -> u8 {
+        match self {
+            Source::Getauxval => 1,
+            Source::Sysconf => 2,
+            Source::ProcAuxv => 3,
+            Source::InitialAuxv => 4,
+            Source::GetSystemInfo => 5,
+            Source::Default => 6,
+        }
+    }
+
+    fn from_code(code: u8) -> Source {
+        match code {
+            1 => Source::Getauxval,
+            2 => Source::Sysconf,
+            3 => Source::ProcAuxv,
+            4 => Source::InitialAuxv,
+            5 => Source::GetSystemInfo,
+            _ => Source::Default,
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Source::Getauxval => "getauxval(AT_PAGESZ)",
+            Source::Sysconf => "sysconf(_SC_PAGESIZE)",
+            Source::ProcAuxv => "/proc/self/auxv",
+            Source::InitialAuxv => "initial auxiliary vector",
+            Source::GetSystemInfo => "GetSystemInfo",
+            Source::Default => "compile-time default",
+        })
+    }
+}
+
+/// The error type returned by the fallible functions of this crate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// `source` failed. `errno` is the OS error code it reported, or `0` if
+    /// it did not report one.
+    Query { source: Source, errno: i32 },
+    /// `source` returned a value that is not a non-zero power of two.
+    InvalidSize { source: Source, value: usize },
+    /// A system call failed. `operation` names the call, and `errno` is the
+    /// OS error code it reported.
+    Os { operation: &'static str, errno: i32 },
+    /// A file provided by the kernel did not have the expected format.
+    /// `what` names the file or the value that could not be parsed.
+    Malformed { what: &'static str },
+    /// `value`, an address, offset or length, is not a multiple of the page
+    /// size.
+    Unaligned { value: usize },
+    /// The range `start..end` is reversed or extends past the memory it
+    /// applies to.
+    OutOfRange { start: usize, end: usize },
+    /// The running kernel does not support `operation`, because it is too
+    /// old or was built without the feature.
+    Unsupported { operation: &'static str },
+    /// Locking pages in memory would exceed the `RLIMIT_MEMLOCK` limit by
+    /// `excess_pages` pages.
+    MemlockLimit { excess_pages: usize },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Query { source, errno: 0 } => write!(f, "{} failed", source),
+            Error::Query { source, errno } => {
+                write!(f, "{} failed (os error {})", source, errno)
+            }
+            Error::Os { operation, errno } => {
+                write!(f, "{} failed (os error {})", operation, errno)
+            }
+            Error::Malformed { what } => write!(f, "cannot parse {}", what),
+            Error::Unaligned { value } => write!(f, "{:#x} is not page-aligned", value),
+            Error::OutOfRange { start, end } => {
+                write!(f, "{:#x}..{:#x} is out of range", start, end)
+            }
+            Error::Unsupported { operation } => {
+                write!(f, "{} is not supported by this kernel", operation)
+            }
+            Error::MemlockLimit { excess_pages } => write!(
+                f,
+                "would exceed the memlock limit by {} pages",
+                excess_pages
+            ),
+            Error::InvalidSize { source, value } => write!(
+                f,
+                "{} returned {}, which is not a power of two",
+                source, value
+            ),
+        }
+    }
+}
+
+#[cfg(not(feature = "no_std"))]
+impl std::error::Error for Error {}
+
+/// The page size assumed when no source can report the real one.
+///
+/// On Linux, this is the last resort of the source chain used by [`get`] and
+/// [`try_get`]; it is also used on targets without any way to query the page
+/// size. It is the smallest page size the architecture supports in its usual
+/// configuration: 64 KiB on 64-bit PowerPC, 16 KiB on 64-bit LoongArch, 8 KiB
+/// on 64-bit SPARC and 4 KiB everywhere else.
+pub const DEFAULT_PAGE_SIZE: usize = if cfg!(target_arch = "powerpc64") {
+    65536
+} else if cfg!(target_arch = "loongarch64") {
+    16384
+} else if cfg!(target_arch = "sparc64") {
+    8192
+} else {
+    4096
+};
+
 /// This function retrieves the system's memory page size.
 ///
+/// # Panics
+///
+/// Panics if the page size cannot be determined. Use [`try_get`] to handle
+/// that case instead.
+///
+/// With the `init-array` feature on Linux, the page size is queried by a
+/// startup constructor, and this function is a plain load that never panics.
+/// If the constructor did not run or failed, it returns
+/// [`DEFAULT_PAGE_SIZE`] until a fallible function such as [`try_get`] has
+/// queried the page size.
+///
 /// # Example
 ///
 /// ```rust
 /// extern crate page_size;
 /// println!("{}", page_size::get());
 /// ```
+#[inline]
 pub fn get() -> usize {
-    get_helper()
+    #[cfg(all(target_os = "linux", feature = "init-array"))]
+    let bytes = init_array::bytes();
+    #[cfg(not(all(target_os = "linux", feature = "init-array")))]
+    let bytes = PAGE_SIZE.bytes(query_page_size);
+
+    bytes
 }
 
 /// This function retrieves the system's memory allocation granularity.
 ///
+/// # Panics
+///
+/// Panics if the granularity cannot be determined. Use
+/// [`try_get_granularity`] to handle that case instead.
+///
 /// # Example
 ///
 /// ```rust
@@ -59,100 +526,620 @@ pub fn get() -> usize {
 /// println!("{}", page_size::get_granularity());
 /// ```
 pub fn get_granularity() -> usize {
-    get_granularity_helper()
+    match get_granularity_helper() {
+        Ok((granularity, _)) => granularity.bytes(),
+        Err(err) => panic!("page_size: {}", err),
+    }
 }
 
-// Unix Section
-
-#[cfg(all(unix, feature = "no_std"))]
+/// This function retrieves the system's memory page size, or the reason it
+/// could not be determined.
+///
+/// Like the value returned by [`get`], a successful result is cached. A
+/// failed query is retried on the next call.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate page_size;
+/// let page_size = page_size::try_get().unwrap();
+/// assert!(page_size.bytes().is_power_of_two());
+/// ```
 #[inline]
-fn get_helper() -> usize {
-    static INIT: Once<usize> = Once::new();
+pub fn try_get() -> Result<PageSize, Error> {
+    get_helper().map(|(page_size, _)| page_size)
+}
+
+/// This function works like [`try_get`], but also reports which [`Source`]
+/// the page size was obtained from.
+///
+/// On Linux, the sources are tried in the following order, and the first one
+/// to produce a valid page size wins:
+///
+/// 1. `getauxval(AT_PAGESZ)`
+/// 2. `sysconf(_SC_PAGESIZE)`
+/// 3. the `AT_PAGESZ` entry of `/proc/self/auxv`
+/// 4. [`DEFAULT_PAGE_SIZE`]
+///
+/// # Example
+///
+/// ```rust
+/// extern crate page_size;
+/// let (page_size, source) = page_size::try_get_with_source().unwrap();
+/// println!("{} (from {})", page_size, source);
+/// ```
+pub fn try_get_with_source() -> Result<(PageSize, Source), Error> {
+    get_helper()
+}
 
-    *INIT.call_once(unix::get)
+/// This function retrieves the system's memory allocation granularity, or the
+/// reason it could not be determined.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate page_size;
+/// let granularity = page_size::try_get_granularity().unwrap();
+/// assert!(granularity.bytes() >= page_size::get());
+/// ```
+pub fn try_get_granularity() -> Result<PageSize, Error> {
+    get_granularity_helper().map(|(granularity, _)| granularity)
+}
+
+// Cache Section
+
+// The page size of the system, or 0 if it has not been queried yet. See
+// `Cache`.
+static PAGE_SIZE: Cache = Cache::new();
+
+type Query = fn() -> Result<(PageSize, Source), Error>;
+
+// A page size and the source it came from, packed into a single word: the low
+// byte holds the shift, the bits above it the code of the source (which is
+// never 0), so that 0 can mean "not queried yet".
+//
+// Reading a cached value is a single relaxed load. Only successful queries are
+// stored, and since every thread that races to initialize the cache stores
+// the same value, no further synchronization is needed.
+struct Cache(AtomicUsize);
+
+impl Cache {
+    const fn new() -> Cache {
+        Cache(AtomicUsize::new(0))
+    }
+
+    #[inline]
+    fn get(&self, query: Query) -> Result<(PageSize, Source), Error> {
+        match self.0.load(Ordering::Relaxed) {
+            0 => self.init(query),
+            state => Ok((
+                PageSize::from_shift(state as u32 & 0xff).unwrap(),
+                Source::from_code((state >> 8) as u8),
+            )),
+        }
+    }
+
+    // Returns only the size in bytes, panicking if it cannot be determined.
+    #[cfg_attr(all(target_os = "linux", feature = "init-array"), allow(dead_code))]
+    #[inline]
+    fn bytes(&self, query: Query) -> usize {
+        match self.0.load(Ordering::Relaxed) {
+            0 => self.init_bytes(query),
+            // The shift is always less than the number of bits in a usize, so
+            // the mask `wrapping_shl` applies (which is free on most targets)
+            // drops exactly the source code.
+            state => 1usize.wrapping_shl(state as u32),
+        }
+    }
+
+    #[cfg_attr(all(target_os = "linux", feature = "init-array"), allow(dead_code))]
+    #[cold]
+    fn init_bytes(&self, query: Query) -> usize {
+        match self.init(query) {
+            Ok((page_size, _)) => page_size.bytes(),
+            Err(err) => panic!("page_size: {}", err),
+        }
+    }
+
+    #[cold]
+    fn init(&self, query: Query) -> Result<(PageSize, Source), Error> {
+        let result = query();
+        if let Ok((page_size, source)) = result {
+            self.set(page_size, source);
+        }
+
+        result
+    }
+
+    fn set(&self, page_size: PageSize, source: Source) {
+        let state = (source.code() as usize) << 8 | page_size.shift() as usize;
+        self.0.store(state, Ordering::Relaxed);
+    }
 }
 
-#[cfg(all(unix, not(feature = "no_std")))]
 #[inline]
-fn get_helper() -> usize {
-    static INIT: Once = Once::new();
-    static mut PAGE_SIZE: usize = 0;
+fn get_helper() -> Result<(PageSize, Source), Error> {
+    PAGE_SIZE.get(query_page_size)
+}
+
+// Unix Section
 
-    unsafe {
-        INIT.call_once(|| PAGE_SIZE = unix::get());
-        PAGE_SIZE
+#[cfg(unix)]
+fn query_page_size() -> Result<(PageSize, Source), Error> {
+    let result = unix::get();
+    #[cfg(all(target_os = "linux", feature = "init-array"))]
+    if let Ok((page_size, _)) = result {
+        init_array::publish(page_size);
     }
+
+    result
 }
 
 // Unix does not have a specific allocation granularity.
 // The page size works well.
 #[cfg(unix)]
 #[inline]
-fn get_granularity_helper() -> usize {
+fn get_granularity_helper() -> Result<(PageSize, Source), Error> {
     get_helper()
 }
 
 #[cfg(unix)]
 mod unix {
-    use libc::{sysconf, _SC_PAGESIZE};
+    use {Error, PageSize, Source};
 
+    #[cfg(all(target_os = "linux", feature = "linux-raw"))]
+    pub(crate) use linux_raw as sys;
+
+    // Linux offers several independent ways to learn the page size, and any
+    // one of them may be unavailable (no libc auxv support in a static
+    // binary, /proc not mounted, sysconf blocked by a seccomp filter, ...).
+    // Try them in order of cost, settling for the compile-time default.
+    #[cfg(target_os = "linux")]
     #[inline]
-    pub fn get() -> usize {
-        unsafe { sysconf(_SC_PAGESIZE) as usize }
+    pub fn get() -> Result<(PageSize, Source), Error> {
+        type Query = fn() -> Result<PageSize, Error>;
+
+        #[cfg(not(feature = "linux-raw"))]
+        let sources: [(Query, Source); 3] = [
+            (getauxval, Source::Getauxval),
+            (sysconf_page_size, Source::Sysconf),
+            (proc_auxv, Source::ProcAuxv),
+        ];
+        #[cfg(feature = "linux-raw")]
+        let sources: [(Query, Source); 2] = [
+            (sys::initial_auxv, Source::InitialAuxv),
+            (proc_auxv, Source::ProcAuxv),
+        ];
+        for &(query, source) in &sources {
+            if let Ok(page_size) = query() {
+                return Ok((page_size, source));
+            }
+        }
+
+        PageSize::verify(::DEFAULT_PAGE_SIZE, Source::Default)
+            .map(|page_size| (page_size, Source::Default))
     }
-}
 
-// WebAssembly section
+    #[cfg(not(target_os = "linux"))]
+    #[inline]
+    pub fn get() -> Result<(PageSize, Source), Error> {
+        sysconf_page_size().map(|page_size| (page_size, Source::Sysconf))
+    }
 
-// WebAssembly does not have a specific allocation granularity.
-// The page size works well.
-#[cfg(all(not(target_os = "emscripten"), any(target_arch = "wasm32", target_arch = "wasm64")))]
-#[inline]
-fn get_granularity_helper() -> usize {
-    // <https://webassembly.github.io/spec/core/exec/runtime.html#page-size>
-    65536
-}
+    #[cfg(not(all(target_os = "linux", feature = "linux-raw")))]
+    pub fn sysconf_page_size() -> Result<PageSize, Error> {
+        use libc::{sysconf, _SC_PAGESIZE};
 
-// Windows Section
+        let value = unsafe { sysconf(_SC_PAGESIZE) };
+        if value == -1 {
+            return Err(Error::Query {
+                source: Source::Sysconf,
+                errno: sys::errno(),
+            });
+        }
 
-#[cfg(all(windows, feature = "no_std"))]
-#[inline]
-fn get_helper() -> usize {
-    static INIT: Once<usize> = Once::new();
+        PageSize::verify(value as usize, Source::Sysconf)
+    }
 
-    *INIT.call_once(windows::get)
-}
+    #[cfg(all(target_os = "linux", not(feature = "linux-raw")))]
+    fn getauxval() -> Result<PageSize, Error> {
+        // getauxval returns 0 (and sets errno to ENOENT) for missing entries.
+        let value = unsafe { ::libc::getauxval(::libc::AT_PAGESZ) };
+        if value == 0 {
+            return Err(Error::Query {
+                source: Source::Getauxval,
+                errno: sys::errno(),
+            });
+        }
 
-#[cfg(all(windows, not(feature = "no_std")))]
-#[inline]
-fn get_helper() -> usize {
-    static INIT: Once = Once::new();
-    static mut PAGE_SIZE: usize = 0;
+        PageSize::verify(value as usize, Source::Getauxval)
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn proc_auxv() -> Result<PageSize, Error> {
+        use core::{mem, slice};
+
+        let query_error = |errno| Error::Query {
+            source: Source::ProcAuxv,
+            errno,
+        };
+
+        let fd = sys::open(b"/proc/self/auxv\0").map_err(query_error)?;
+
+        // The file is a sequence of (type, value) pairs of native words. Since
+        // the buffer holds a whole number of pairs, reading until it is full
+        // (or the file ends) keeps every chunk aligned on a pair.
+        let mut result = Err(query_error(0));
+        let mut buf = [0usize; 64];
+        loop {
+            let size = mem::size_of_val(&buf);
+            let bytes = unsafe { slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut u8, size) };
+            let filled = match sys::read_full(fd, bytes) {
+                Ok(filled) => filled,
+                Err(errno) => {
+                    result = Err(query_error(errno));
+                    break;
+                }
+            };
+
+            let words = filled / mem::size_of::<usize>();
+            if let Some(found) = auxv_page_size(&buf[..words]) {
+                result = found
+                    .ok_or_else(|| query_error(0))
+                    .and_then(|value| PageSize::verify(value, Source::ProcAuxv));
+                break;
+            }
+
+            if filled < size {
+                break;
+            }
+        }
+
+        sys::close(fd);
+        result
+    }
+
+    /// Returns the `AT_PAGESZ` entry of the auxiliary vector at `auxv`.
+    ///
+    /// # Safety
+    ///
+    /// `auxv` must point to a readable sequence of `(type, value)` pairs of
+    /// native words terminated by an `AT_NULL` entry.
+    #[cfg(all(target_os = "linux", feature = "linux-raw"))]
+    pub unsafe fn auxv_ptr_page_size(auxv: *const usize) -> Option<usize> {
+        let mut entry = auxv;
+        loop {
+            let pair = ::core::slice::from_raw_parts(entry, 2);
+            if let Some(found) = auxv_page_size(pair) {
+                return found;
+            }
+            entry = entry.add(2);
+        }
+    }
+
+    /// Scans auxiliary vector entries for `AT_PAGESZ`.
+    ///
+    /// Returns `None` if `entries` ran out before the end of the vector,
+    /// `Some(None)` if the vector ended without an `AT_PAGESZ` entry.
+    #[cfg(target_os = "linux")]
+    pub fn auxv_page_size(entries: &[usize]) -> Option<Option<usize>> {
+        const AT_NULL: usize = 0;
+        const AT_PAGESZ: usize = 6;
+
+        for pair in entries.chunks_exact(2) {
+            match pair[0] {
+                AT_NULL => return Some(None),
+                AT_PAGESZ => return Some(Some(pair[1])),
+                _ => {}
+            }
+        }
+
+        None
+    }
+
+    #[cfg(not(all(target_os = "linux", feature = "linux-raw")))]
+    pub mod sys {
+        #[cfg(target_os = "linux")]
+        use libc::{c_void, O_CLOEXEC, O_RDONLY};
+
+        /// Opens the NUL-terminated `path` for reading.
+        #[cfg(target_os = "linux")]
+        pub fn open(path: &[u8]) -> Result<i32, i32> {
+            debug_assert_eq!(path.last(), Some(&0));
+
+            match unsafe { ::libc::open(path.as_ptr() as *const _, O_RDONLY | O_CLOEXEC) } {
+                -1 => Err(errno()),
+                fd => Ok(fd),
+            }
+        }
+
+        /// Reads from `fd` until `buf` is full or the end of the file is
+        /// reached, returning the number of bytes read.
+        #[cfg(target_os = "linux")]
+        pub fn read_full(fd: i32, buf: &mut [u8]) -> Result<usize, i32> {
+            let mut filled = 0;
+            while filled < buf.len() {
+                let rest = &mut buf[filled..];
+                match unsafe { ::libc::read(fd, rest.as_mut_ptr() as *mut c_void, rest.len()) } {
+                    -1 if errno() == ::libc::EINTR => continue,
+                    -1 => return Err(errno()),
+                    0 => break,
+                    count => filled += count as usize,
+                }
+            }
+
+            Ok(filled)
+        }
+
+        /// Reads `linux_dirent64` records of the directory `fd` into `buf`,
+        /// returning the number of bytes read (0 at the end of the directory).
+        #[cfg(target_os = "linux")]
+        pub fn getdents64(fd: i32, buf: &mut [u8]) -> Result<usize, i32> {
+            let count = unsafe {
+                ::libc::syscall(
+                    ::libc::SYS_getdents64,
+                    fd,
+                    buf.as_mut_ptr() as *mut c_void,
+                    buf.len(),
+                )
+            };
+            match count {
+                -1 => Err(errno()),
+                count => Ok(count as usize),
+            }
+        }
+
+        #[cfg(target_os = "linux")]
+        pub fn close(fd: i32) {
+            unsafe { ::libc::close(fd) };
+        }
+
+        // Only the tests check for it.
+        #[cfg(all(target_os = "linux", test))]
+        pub use libc::EBADF;
+
+        /// Returns the size of the file `fd` in bytes.
+        #[cfg(target_os = "linux")]
+        pub fn file_size(fd: i32) -> Result<u64, i32> {
+            let mut stat = unsafe { ::core::mem::zeroed::<::libc::stat64>() };
+            match unsafe { ::libc::fstat64(fd, &mut stat) } {
+                -1 => Err(errno()),
+                _ => Ok(stat.st_size as u64),
+            }
+        }
+
+        #[cfg(target_os = "linux")]
+        pub use libc::{
+            EAGAIN, EINVAL, ENOENT, ENOMEM, EPERM, MADV_COLD, MADV_DODUMP, MADV_DOFORK,
+            MADV_DONTDUMP, MADV_DONTFORK, MADV_DONTNEED, MADV_FREE, MADV_HUGEPAGE, MADV_KEEPONFORK,
+            MADV_MERGEABLE, MADV_NOHUGEPAGE, MADV_PAGEOUT, MADV_POPULATE_READ, MADV_POPULATE_WRITE,
+            MADV_UNMERGEABLE, MADV_WILLNEED, MADV_WIPEONFORK, MAP_ANONYMOUS, MAP_FIXED,
+            MAP_HUGETLB, MAP_HUGE_SHIFT, MAP_PRIVATE, MAP_SHARED, MCL_CURRENT, MCL_FUTURE,
+            POSIX_FADV_DONTNEED, POSIX_FADV_NORMAL, POSIX_FADV_RANDOM, POSIX_FADV_SEQUENTIAL,
+            POSIX_FADV_WILLNEED, PROT_EXEC, PROT_NONE, PROT_READ, PROT_WRITE,
+        };
+
+        /// Maps `len` bytes of `fd` at `offset`, or anonymous memory if `fd`
+        /// is -1.
+        #[cfg(target_os = "linux")]
+        pub unsafe fn mmap(
+            addr: *mut u8,
+            len: usize,
+            prot: i32,
+            flags: i32,
+            fd: i32,
+            offset: u64,
+        ) -> Result<*mut u8, i32> {
+            // `off_t` is 32 bits wide on 32-bit glibc targets.
+            match ::libc::mmap64(
+                addr as *mut c_void,
+                len,
+                prot,
+                flags,
+                fd,
+                offset as ::libc::off64_t,
+            ) {
+                ::libc::MAP_FAILED => Err(errno()),
+                ptr => Ok(ptr as *mut u8),
+            }
+        }
+
+        #[cfg(target_os = "linux")]
+        pub unsafe fn mprotect(addr: *mut u8, len: usize, prot: i32) -> Result<(), i32> {
+            match ::libc::mprotect(addr as *mut c_void, len, prot) {
+                -1 => Err(errno()),
+                _ => Ok(()),
+            }
+        }
+
+        #[cfg(target_os = "linux")]
+        pub unsafe fn madvise(addr: *mut u8, len: usize, advice: i32) -> Result<(), i32> {
+            match ::libc::madvise(addr as *mut c_void, len, advice) {
+                -1 => Err(errno()),
+                _ => Ok(()),
+            }
+        }
 
-    unsafe {
-        INIT.call_once(|| PAGE_SIZE = windows::get());
-        PAGE_SIZE
+        /// Reports in `vec`, one byte per page, which pages of `addr..addr + len`
+        /// are resident. `vec` must have room for every page.
+        #[cfg(target_os = "linux")]
+        pub unsafe fn mincore(addr: *mut u8, len: usize, vec: &mut [u8]) -> Result<(), i32> {
+            match ::libc::mincore(addr as *mut c_void, len, vec.as_mut_ptr()) {
+                -1 => Err(errno()),
+                _ => Ok(()),
+            }
+        }
+
+        /// Advises the kernel about the use of `len` bytes of `fd` at `offset`,
+        /// or up to the end of the file if `len` is 0.
+        #[cfg(target_os = "linux")]
+        pub fn fadvise(fd: i32, offset: u64, len: u64, advice: i32) -> Result<(), i32> {
+            // `off_t` is 32 bits wide on 32-bit glibc targets.
+            let (offset, len) = (offset as ::libc::off64_t, len as ::libc::off64_t);
+            match unsafe { ::libc::posix_fadvise64(fd, offset, len, advice) } {
+                0 => Ok(()),
+                errno => Err(errno),
+            }
+        }
+
+        #[cfg(target_os = "linux")]
+        pub fn readahead(fd: i32, offset: u64, count: usize) -> Result<(), i32> {
+            match unsafe { ::libc::readahead(fd, offset as _, count) } {
+                -1 => Err(errno()),
+                _ => Ok(()),
+            }
+        }
+
+        /// The `mlock2` flag that locks pages as they are faulted in.
+        #[cfg(target_os = "linux")]
+        pub const MLOCK_ONFAULT: i32 = ::libc::MLOCK_ONFAULT as i32;
+
+        /// Locks the pages of `addr..addr + len` in memory, with `mlock2`
+        /// if `flags` is not 0.
+        #[cfg(target_os = "linux")]
+        pub fn mlock(addr: usize, len: usize, flags: i32) -> Result<(), i32> {
+            let ret = unsafe {
+                match flags {
+                    0 => ::libc::mlock(addr as *const c_void, len),
+                    flags => mlock2(addr, len, flags),
+                }
+            };
+            match ret {
+                -1 => Err(errno()),
+                _ => Ok(()),
+            }
+        }
+
+        #[cfg(all(target_os = "linux", any(target_env = "gnu", target_env = "musl")))]
+        unsafe fn mlock2(addr: usize, len: usize, flags: i32) -> i32 {
+            ::libc::mlock2(addr as *const c_void, len, flags as ::libc::c_uint)
+        }
+
+        // Other C libraries may not have a wrapper for `mlock2`.
+        #[cfg(all(target_os = "linux", not(any(target_env = "gnu", target_env = "musl"))))]
+        unsafe fn mlock2(addr: usize, len: usize, flags: i32) -> i32 {
+            ::libc::syscall(::libc::SYS_mlock2, addr, len, flags) as i32
+        }
+
+        #[cfg(target_os = "linux")]
+        pub fn munlock(addr: usize, len: usize) -> Result<(), i32> {
+            match unsafe { ::libc::munlock(addr as *const c_void, len) } {
+                -1 => Err(errno()),
+                _ => Ok(()),
+            }
+        }
+
+        #[cfg(target_os = "linux")]
+        pub fn mlockall(flags: i32) -> Result<(), i32> {
+            match unsafe { ::libc::mlockall(flags) } {
+                -1 => Err(errno()),
+                _ => Ok(()),
+            }
+        }
+
+        #[cfg(target_os = "linux")]
+        pub fn munlockall() -> Result<(), i32> {
+            match unsafe { ::libc::munlockall() } {
+                -1 => Err(errno()),
+                _ => Ok(()),
+            }
+        }
+
+        /// Returns the soft `RLIMIT_MEMLOCK` limit in bytes, or `None` if
+        /// there is none.
+        #[cfg(target_os = "linux")]
+        #[allow(clippy::unnecessary_cast)]
+        pub fn memlock_limit() -> Result<Option<u64>, i32> {
+            let mut limit = ::libc::rlimit {
+                rlim_cur: 0,
+                rlim_max: 0,
+            };
+            match unsafe { ::libc::getrlimit(::libc::RLIMIT_MEMLOCK, &mut limit) } {
+                -1 => Err(errno()),
+                _ if limit.rlim_cur == ::libc::RLIM_INFINITY => Ok(None),
+                // `rlim_t` is 32 bits wide on 32-bit glibc targets.
+                _ => Ok(Some(limit.rlim_cur as u64)),
+            }
+        }
+
+        #[cfg(target_os = "linux")]
+        pub unsafe fn munmap(addr: *mut u8, len: usize) -> Result<(), i32> {
+            match ::libc::munmap(addr as *mut c_void, len) {
+                -1 => Err(errno()),
+                _ => Ok(()),
+            }
+        }
+
+        #[cfg(not(feature = "no_std"))]
+        pub fn errno() -> i32 {
+            ::std::io::Error::last_os_error()
+                .raw_os_error()
+                .unwrap_or(0)
+        }
+
+        #[cfg(all(feature = "no_std", any(target_os = "linux", target_os = "emscripten")))]
+        pub fn errno() -> i32 {
+            unsafe { *::libc::__errno_location() }
+        }
+
+        #[cfg(all(feature = "no_std", target_os = "android"))]
+        pub fn errno() -> i32 {
+            unsafe { *::libc::__errno() }
+        }
+
+        #[cfg(all(
+            feature = "no_std",
+            any(target_os = "macos", target_os = "ios", target_os = "freebsd")
+        ))]
+        pub fn errno() -> i32 {
+            unsafe { *::libc::__error() }
+        }
+
+        // Other platforms expose errno under names we do not track here.
+        #[cfg(all(
+            feature = "no_std",
+            not(any(
+                target_os = "linux",
+                target_os = "emscripten",
+                target_os = "android",
+                target_os = "macos",
+                target_os = "ios",
+                target_os = "freebsd"
+            ))
+        ))]
+        pub fn errno() -> i32 {
+            0
+        }
     }
 }
 
-#[cfg(all(windows, feature = "no_std"))]
+// WebAssembly section
+
+// WebAssembly does not have a specific allocation granularity.
+// The page size works well.
+#[cfg(all(
+    not(target_os = "emscripten"),
+    any(target_arch = "wasm32", target_arch = "wasm64")
+))]
 #[inline]
-fn get_granularity_helper() -> usize {
-    static GRINIT: Once<usize> = Once::new();
+fn get_granularity_helper() -> Result<(PageSize, Source), Error> {
+    // <https://webassembly.github.io/spec/core/exec/runtime.html#page-size>
+    PageSize::verify(65536, Source::Default).map(|granularity| (granularity, Source::Default))
+}
 
-    *GRINIT.call_once(windows::get_granularity)
+// Windows Section
+
+#[cfg(windows)]
+fn query_page_size() -> Result<(PageSize, Source), Error> {
+    windows::get()
 }
 
-#[cfg(all(windows, not(feature = "no_std")))]
+#[cfg(windows)]
 #[inline]
-fn get_granularity_helper() -> usize {
-    static GRINIT: Once = Once::new();
-    static mut GRANULARITY: usize = 0;
+fn get_granularity_helper() -> Result<(PageSize, Source), Error> {
+    static GRANULARITY: Cache = Cache::new();
 
-    unsafe {
-        GRINIT.call_once(|| GRANULARITY = windows::get_granularity());
-        GRANULARITY
-    }
+    GRANULARITY.get(windows::get_granularity)
 }
 
 #[cfg(windows)]
@@ -165,33 +1152,41 @@ mod windows {
     use winapi::um::sysinfoapi::GetSystemInfo;
     use winapi::um::sysinfoapi::{LPSYSTEM_INFO, SYSTEM_INFO};
 
+    use {Error, PageSize, Source};
+
     #[inline]
-    pub fn get() -> usize {
-        unsafe {
+    pub fn get() -> Result<(PageSize, Source), Error> {
+        let page_size = unsafe {
             let mut info: SYSTEM_INFO = mem::zeroed();
             GetSystemInfo(&mut info as LPSYSTEM_INFO);
 
             info.dwPageSize as usize
-        }
+        };
+
+        PageSize::verify(page_size, Source::GetSystemInfo)
+            .map(|page_size| (page_size, Source::GetSystemInfo))
     }
 
     #[inline]
-    pub fn get_granularity() -> usize {
-        unsafe {
+    pub fn get_granularity() -> Result<(PageSize, Source), Error> {
+        let granularity = unsafe {
             let mut info: SYSTEM_INFO = mem::zeroed();
             GetSystemInfo(&mut info as LPSYSTEM_INFO);
 
             info.dwAllocationGranularity as usize
-        }
+        };
+
+        PageSize::verify(granularity, Source::GetSystemInfo)
+            .map(|granularity| (granularity, Source::GetSystemInfo))
     }
 }
 
 // Stub Section
 
 #[cfg(not(any(unix, windows)))]
-#[inline]
-fn get_helper() -> usize {
-    4096 // 4k is the default on many systems
+fn query_page_size() -> Result<(PageSize, Source), Error> {
+    PageSize::verify(DEFAULT_PAGE_SIZE, Source::Default)
+        .map(|page_size| (page_size, Source::Default))
 }
 
 #[cfg(test)]
@@ -209,4 +1204,110 @@ mod tests {
         #[allow(unused_variables)]
         let granularity = get_granularity();
     }
+
+    #[test]
+    fn test_try_get() {
+        let page_size = try_get().unwrap();
+        assert!(page_size.bytes().is_power_of_two());
+        assert_eq!(page_size.bytes(), get());
+        assert!(try_get_granularity().unwrap() >= page_size);
+    }
+
+    #[test]
+    fn test_try_get_with_source() {
+        let (page_size, source) = try_get_with_source().unwrap();
+        assert_eq!(page_size.bytes(), get());
+        #[cfg(all(target_os = "linux", not(feature = "linux-raw")))]
+        assert_eq!(source, Source::Getauxval);
+        #[cfg(not(all(target_os = "linux", not(feature = "linux-raw"))))]
+        let _ = source;
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_linux_sources_agree() {
+        let page_size = get();
+        assert_eq!(unix::proc_auxv().map(PageSize::bytes), Ok(page_size));
+        #[cfg(not(feature = "linux-raw"))]
+        assert_eq!(
+            unix::sysconf_page_size().map(PageSize::bytes),
+            Ok(page_size)
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_auxv_page_size() {
+        assert_eq!(
+            unix::auxv_page_size(&[3, 64, 6, 4096, 0, 0]),
+            Some(Some(4096))
+        );
+        assert_eq!(unix::auxv_page_size(&[3, 64, 0, 0, 6, 4096]), Some(None));
+        assert_eq!(unix::auxv_page_size(&[3, 64, 7]), None);
+    }
+
+    #[test]
+    fn test_cache() {
+        fn query() -> Result<(PageSize, Source), Error> {
+            Ok((PageSize::new(16384).unwrap(), Source::ProcAuxv))
+        }
+        fn fail() -> Result<(PageSize, Source), Error> {
+            Err(Error::Query {
+                source: Source::Sysconf,
+                errno: 22,
+            })
+        }
+
+        let cache = Cache::new();
+        assert_eq!(cache.get(fail), fail());
+        assert_eq!(cache.get(query), query());
+        assert_eq!(cache.get(fail), query());
+        assert_eq!(cache.bytes(fail), 16384);
+    }
+
+    #[cfg(all(target_os = "linux", feature = "init-array"))]
+    #[test]
+    fn test_init_array() {
+        // The constructor has already filled the cache.
+        assert_ne!(PAGE_SIZE.0.load(Ordering::Relaxed), 0);
+        let (page_size, source) = try_get_with_source().unwrap();
+        assert_eq!(init_array::bytes(), page_size.bytes());
+        assert_eq!(unix::proc_auxv(), Ok(page_size));
+        #[cfg(all(feature = "linux-raw", target_env = "gnu"))]
+        assert_eq!(source, Source::InitialAuxv);
+        #[cfg(not(feature = "linux-raw"))]
+        assert_eq!(source, Source::Getauxval);
+    }
+
+    #[cfg(target_arch = "sparc64")]
+    #[test]
+    fn test_default_page_size_sparc64() {
+        assert_eq!(DEFAULT_PAGE_SIZE, 8192);
+    }
+
+    #[test]
+    fn test_page_size_new() {
+        assert_eq!(PageSize::new(4096).map(PageSize::bytes), Some(4096));
+        assert_eq!(PageSize::new(0), None);
+        assert_eq!(PageSize::new(4095), None);
+        assert_eq!(PageSize::new(usize::MAX), None);
+    }
+
+    #[test]
+    fn test_page_size_arithmetic() {
+        let page_size = PageSize::get();
+        assert_eq!(page_size.bytes(), get());
+        assert_eq!(1 << page_size.shift(), page_size.bytes());
+        assert_eq!(page_size.mask(), page_size.bytes() - 1);
+
+        let bytes = 5 * page_size.bytes() + 17;
+        assert_eq!(bytes / page_size, 5);
+        assert_eq!(bytes % page_size, 17);
+        assert_eq!(bytes as u64 / page_size, 5);
+        assert_eq!(bytes as u64 % page_size, 17);
+        assert_eq!(5 * page_size, 5 * page_size.bytes());
+        assert_eq!(page_size.checked_mul(usize::MAX), None);
+        assert_eq!(PageSize::from_shift(12), PageSize::new(4096));
+        assert_eq!(PageSize::from_shift(usize::BITS), None);
+    }
 }
@@ -0,0 +1,484 @@
+//! Page size discovery for Linux without libc.
+//!
+//! With the `linux-raw` feature, this crate does not use libc on Linux, and
+//! with the default `libc` feature disabled, libc is not built at all.
+//! Instead, the page size is taken from the auxiliary vector handed to
+//! [`init_from_auxv`] by the program's entry point, or else read from
+//! `/proc/self/auxv` using raw system calls. The memory mapping types go
+//! through the same raw system calls.
+//!
+//! Raw system calls are implemented for `x86_64`, `aarch64` and `riscv64`.
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use {Error, PageSize, Source};
+
+// The AT_PAGESZ value recorded by `init_from_auxv`, or 0.
+static INITIAL_AUXV_PAGE_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+/// Records the page size from the program's initial auxiliary vector.
+///
+/// Freestanding programs that define their own entry point can call this
+/// before the first call to [`get`](::get) so that the page size does not
+/// have to be read from `/proc/self/auxv`. On entry, the auxiliary vector
+/// starts right after the `NULL` that terminates the environment pointers on
+/// the initial stack.
+///
+/// # Safety
+///
+/// `auxv` must point to a readable sequence of `(type, value)` pairs of
+/// native words terminated by an `AT_NULL` entry.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// extern crate page_size;
+///
+/// // `sp` is the stack pointer on entry to `_start`.
+/// unsafe fn start(sp: *const usize) {
+///     let argc = *sp;
+///     let envp = sp.add(argc + 2);
+///     let mut auxv = envp;
+///     while *auxv != 0 {
+///         auxv = auxv.add(1);
+///     }
+///     page_size::init_from_auxv(auxv.add(1));
+/// }
+/// ```
+pub unsafe fn init_from_auxv(auxv: *const usize) {
+    if let Some(page_size) = ::unix::auxv_ptr_page_size(auxv) {
+        INITIAL_AUXV_PAGE_SIZE.store(page_size, Ordering::Relaxed);
+    }
+}
+
+pub fn initial_auxv() -> Result<PageSize, Error> {
+    match INITIAL_AUXV_PAGE_SIZE.load(Ordering::Relaxed) {
+        0 => Err(Error::Query {
+            source: Source::InitialAuxv,
+            errno: 0,
+        }),
+        value => PageSize::verify(value, Source::InitialAuxv),
+    }
+}
+
+/// Opens the NUL-terminated `path` for reading.
+pub fn open(path: &[u8]) -> Result<i32, i32> {
+    const AT_FDCWD: isize = -100;
+    const O_RDONLY: usize = 0;
+    const O_CLOEXEC: usize = 0o2000000;
+
+    debug_assert_eq!(path.last(), Some(&0));
+
+    let ret = unsafe {
+        syscall4(
+            nr::OPENAT,
+            AT_FDCWD as usize,
+            path.as_ptr() as usize,
+            O_RDONLY | O_CLOEXEC,
+            0,
+        )
+    };
+    check(ret).map(|fd| fd as i32)
+}
+
+/// Reads from `fd` until `buf` is full or the end of the file is reached,
+/// returning the number of bytes read.
+pub fn read_full(fd: i32, buf: &mut [u8]) -> Result<usize, i32> {
+    const EINTR: i32 = 4;
+
+    let mut filled = 0;
+    while filled < buf.len() {
+        let rest = &mut buf[filled..];
+        let ret = unsafe {
+            syscall4(
+                nr::READ,
+                fd as usize,
+                rest.as_mut_ptr() as usize,
+                rest.len(),
+                0,
+            )
+        };
+        match check(ret) {
+            Err(EINTR) => continue,
+            Err(errno) => return Err(errno),
+            Ok(0) => break,
+            Ok(count) => filled += count,
+        }
+    }
+
+    Ok(filled)
+}
+
+/// Reads `linux_dirent64` records of the directory `fd` into `buf`, returning
+/// the number of bytes read (0 at the end of the directory).
+pub fn getdents64(fd: i32, buf: &mut [u8]) -> Result<usize, i32> {
+    let ret = unsafe {
+        syscall4(
+            nr::GETDENTS64,
+            fd as usize,
+            buf.as_mut_ptr() as usize,
+            buf.len(),
+            0,
+        )
+    };
+    check(ret)
+}
+
+pub fn close(fd: i32) {
+    unsafe { syscall4(nr::CLOSE, fd as usize, 0, 0, 0) };
+}
+
+/// Returns the size of the file `fd` in bytes.
+pub fn file_size(fd: i32) -> Result<u64, i32> {
+    // `struct stat`, which has `st_size` at byte offset 48 on every supported
+    // architecture.
+    let mut stat = [0u64; 18];
+    let ret = unsafe { syscall4(nr::FSTAT, fd as usize, stat.as_mut_ptr() as usize, 0, 0) };
+    check(ret).map(|_| stat[6])
+}
+
+pub const EPERM: i32 = 1;
+pub const ENOENT: i32 = 2;
+#[cfg(test)]
+pub const EBADF: i32 = 9;
+pub const EAGAIN: i32 = 11;
+pub const ENOMEM: i32 = 12;
+pub const EINVAL: i32 = 22;
+pub const PROT_NONE: i32 = 0;
+pub const PROT_READ: i32 = 1;
+pub const PROT_WRITE: i32 = 2;
+pub const PROT_EXEC: i32 = 4;
+pub const MAP_SHARED: i32 = 0x01;
+pub const MAP_PRIVATE: i32 = 0x02;
+pub const MAP_FIXED: i32 = 0x10;
+pub const MAP_ANONYMOUS: i32 = 0x20;
+pub const MAP_HUGETLB: i32 = 0x40000;
+pub const MAP_HUGE_SHIFT: i32 = 26;
+pub const MADV_WILLNEED: i32 = 3;
+pub const MADV_DONTNEED: i32 = 4;
+pub const MADV_FREE: i32 = 8;
+pub const MADV_DONTFORK: i32 = 10;
+pub const MADV_DOFORK: i32 = 11;
+pub const MADV_MERGEABLE: i32 = 12;
+pub const MADV_UNMERGEABLE: i32 = 13;
+pub const MADV_HUGEPAGE: i32 = 14;
+pub const MADV_NOHUGEPAGE: i32 = 15;
+pub const MADV_DONTDUMP: i32 = 16;
+pub const MADV_DODUMP: i32 = 17;
+pub const MADV_WIPEONFORK: i32 = 18;
+pub const MADV_KEEPONFORK: i32 = 19;
+pub const MADV_COLD: i32 = 20;
+pub const MADV_PAGEOUT: i32 = 21;
+pub const MADV_POPULATE_READ: i32 = 22;
+pub const MADV_POPULATE_WRITE: i32 = 23;
+pub const MLOCK_ONFAULT: i32 = 1;
+pub const MCL_CURRENT: i32 = 1;
+pub const MCL_FUTURE: i32 = 2;
+pub const POSIX_FADV_NORMAL: i32 = 0;
+pub const POSIX_FADV_RANDOM: i32 = 1;
+pub const POSIX_FADV_SEQUENTIAL: i32 = 2;
+pub const POSIX_FADV_WILLNEED: i32 = 3;
+pub const POSIX_FADV_DONTNEED: i32 = 4;
+
+/// Maps `len` bytes of `fd` at `offset`, or anonymous memory if `fd` is -1.
+pub unsafe fn mmap(
+    addr: *mut u8,
+    len: usize,
+    prot: i32,
+    flags: i32,
+    fd: i32,
+    offset: u64,
+) -> Result<*mut u8, i32> {
+    let ret = syscall6(
+        nr::MMAP,
+        addr as usize,
+        len,
+        prot as usize,
+        flags as usize,
+        fd as usize,
+        offset as usize,
+    );
+    check(ret).map(|addr| addr as *mut u8)
+}
+
+pub unsafe fn mprotect(addr: *mut u8, len: usize, prot: i32) -> Result<(), i32> {
+    check(syscall4(nr::MPROTECT, addr as usize, len, prot as usize, 0)).map(drop)
+}
+
+pub unsafe fn madvise(addr: *mut u8, len: usize, advice: i32) -> Result<(), i32> {
+    check(syscall4(
+        nr::MADVISE,
+        addr as usize,
+        len,
+        advice as usize,
+        0,
+    ))
+    .map(drop)
+}
+
+/// Reports in `vec`, one byte per page, which pages of `addr..addr + len` are
+/// resident. `vec` must have room for every page.
+pub unsafe fn mincore(addr: *mut u8, len: usize, vec: &mut [u8]) -> Result<(), i32> {
+    let ret = syscall4(
+        nr::MINCORE,
+        addr as usize,
+        len,
+        vec.as_mut_ptr() as usize,
+        0,
+    );
+    check(ret).map(drop)
+}
+
+/// Advises the kernel about the use of `len` bytes of `fd` at `offset`, or up
+/// to the end of the file if `len` is 0.
+pub fn fadvise(fd: i32, offset: u64, len: u64, advice: i32) -> Result<(), i32> {
+    let ret = unsafe {
+        syscall4(
+            nr::FADVISE64,
+            fd as usize,
+            offset as usize,
+            len as usize,
+            advice as usize,
+        )
+    };
+    check(ret).map(drop)
+}
+
+pub fn readahead(fd: i32, offset: u64, count: usize) -> Result<(), i32> {
+    let ret = unsafe { syscall4(nr::READAHEAD, fd as usize, offset as usize, count, 0) };
+    check(ret).map(drop)
+}
+
+/// Locks the pages of `addr..addr + len` in memory, with `mlock2` if `flags`
+/// is not 0.
+pub fn mlock(addr: usize, len: usize, flags: i32) -> Result<(), i32> {
+    let ret = unsafe {
+        match flags {
+            0 => syscall4(nr::MLOCK, addr, len, 0, 0),
+            flags => syscall4(nr::MLOCK2, addr, len, flags as usize, 0),
+        }
+    };
+    check(ret).map(drop)
+}
+
+pub fn munlock(addr: usize, len: usize) -> Result<(), i32> {
+    check(unsafe { syscall4(nr::MUNLOCK, addr, len, 0, 0) }).map(drop)
+}
+
+pub fn mlockall(flags: i32) -> Result<(), i32> {
+    check(unsafe { syscall4(nr::MLOCKALL, flags as usize, 0, 0, 0) }).map(drop)
+}
+
+pub fn munlockall() -> Result<(), i32> {
+    check(unsafe { syscall4(nr::MUNLOCKALL, 0, 0, 0, 0) }).map(drop)
+}
+
+/// Returns the soft `RLIMIT_MEMLOCK` limit in bytes, or `None` if there is
+/// none.
+pub fn memlock_limit() -> Result<Option<u64>, i32> {
+    const RLIMIT_MEMLOCK: usize = 8;
+    const RLIM_INFINITY: u64 = !0;
+
+    // `struct rlimit64`: the soft and hard limits.
+    let mut limit = [0u64; 2];
+    let ret = unsafe {
+        syscall4(
+            nr::PRLIMIT64,
+            0,
+            RLIMIT_MEMLOCK,
+            0,
+            limit.as_mut_ptr() as usize,
+        )
+    };
+    check(ret).map(|_| match limit[0] {
+        RLIM_INFINITY => None,
+        soft => Some(soft),
+    })
+}
+
+pub unsafe fn munmap(addr: *mut u8, len: usize) -> Result<(), i32> {
+    check(syscall4(nr::MUNMAP, addr as usize, len, 0, 0)).map(drop)
+}
+
+// The kernel reports errors as return values in [-4095, -1].
+#[inline]
+fn check(ret: isize) -> Result<usize, i32> {
+    if (-4095..0).contains(&ret) {
+        Err(-ret as i32)
+    } else {
+        Ok(ret as usize)
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod nr {
+    pub const READ: usize = 0;
+    pub const CLOSE: usize = 3;
+    pub const FSTAT: usize = 5;
+    pub const MMAP: usize = 9;
+    pub const MPROTECT: usize = 10;
+    pub const MUNMAP: usize = 11;
+    pub const MINCORE: usize = 27;
+    pub const MADVISE: usize = 28;
+    pub const MLOCK: usize = 149;
+    pub const MUNLOCK: usize = 150;
+    pub const MLOCKALL: usize = 151;
+    pub const MUNLOCKALL: usize = 152;
+    pub const READAHEAD: usize = 187;
+    pub const GETDENTS64: usize = 217;
+    pub const FADVISE64: usize = 221;
+    pub const OPENAT: usize = 257;
+    pub const PRLIMIT64: usize = 302;
+    pub const MLOCK2: usize = 325;
+}
+
+// aarch64 and riscv64 use the generic system call table.
+#[cfg(any(target_arch = "aarch64", target_arch = "riscv64"))]
+mod nr {
+    pub const OPENAT: usize = 56;
+    pub const CLOSE: usize = 57;
+    pub const GETDENTS64: usize = 61;
+    pub const READ: usize = 63;
+    pub const FSTAT: usize = 80;
+    pub const READAHEAD: usize = 213;
+    pub const MUNMAP: usize = 215;
+    pub const MMAP: usize = 222;
+    pub const FADVISE64: usize = 223;
+    pub const MPROTECT: usize = 226;
+    pub const MLOCK: usize = 228;
+    pub const MUNLOCK: usize = 229;
+    pub const MLOCKALL: usize = 230;
+    pub const MUNLOCKALL: usize = 231;
+    pub const MINCORE: usize = 232;
+    pub const MADVISE: usize = 233;
+    pub const PRLIMIT64: usize = 261;
+    pub const MLOCK2: usize = 284;
+}
+
+#[cfg(target_arch = "x86_64")]
+#[inline]
+unsafe fn syscall4(nr: usize, a: usize, b: usize, c: usize, d: usize) -> isize {
+    let ret: isize;
+    asm!(
+        "syscall",
+        inlateout("rax") nr as isize => ret,
+        in("rdi") a,
+        in("rsi") b,
+        in("rdx") c,
+        in("r10") d,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack),
+    );
+    ret
+}
+
+#[cfg(target_arch = "x86_64")]
+#[inline]
+unsafe fn syscall6(nr: usize, a: usize, b: usize, c: usize, d: usize, e: usize, f: usize) -> isize {
+    let ret: isize;
+    asm!(
+        "syscall",
+        inlateout("rax") nr as isize => ret,
+        in("rdi") a,
+        in("rsi") b,
+        in("rdx") c,
+        in("r10") d,
+        in("r8") e,
+        in("r9") f,
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack),
+    );
+    ret
+}
+
+#[cfg(target_arch = "aarch64")]
+#[inline]
+unsafe fn syscall4(nr: usize, a: usize, b: usize, c: usize, d: usize) -> isize {
+    let ret: isize;
+    asm!(
+        "svc 0",
+        in("x8") nr,
+        inlateout("x0") a as isize => ret,
+        in("x1") b,
+        in("x2") c,
+        in("x3") d,
+        options(nostack),
+    );
+    ret
+}
+
+#[cfg(target_arch = "aarch64")]
+#[inline]
+unsafe fn syscall6(nr: usize, a: usize, b: usize, c: usize, d: usize, e: usize, f: usize) -> isize {
+    let ret: isize;
+    asm!(
+        "svc 0",
+        in("x8") nr,
+        inlateout("x0") a as isize => ret,
+        in("x1") b,
+        in("x2") c,
+        in("x3") d,
+        in("x4") e,
+        in("x5") f,
+        options(nostack),
+    );
+    ret
+}
+
+#[cfg(target_arch = "riscv64")]
+#[inline]
+unsafe fn syscall4(nr: usize, a: usize, b: usize, c: usize, d: usize) -> isize {
+    let ret: isize;
+    asm!(
+        "ecall",
+        in("a7") nr,
+        inlateout("a0") a as isize => ret,
+        in("a1") b,
+        in("a2") c,
+        in("a3") d,
+        options(nostack),
+    );
+    ret
+}
+
+#[cfg(target_arch = "riscv64")]
+#[inline]
+unsafe fn syscall6(nr: usize, a: usize, b: usize, c: usize, d: usize, e: usize, f: usize) -> isize {
+    let ret: isize;
+    asm!(
+        "ecall",
+        in("a7") nr,
+        inlateout("a0") a as isize => ret,
+        in("a1") b,
+        in("a2") c,
+        in("a3") d,
+        in("a4") e,
+        in("a5") f,
+        options(nostack),
+    );
+    ret
+}
+
+#[cfg(not(any(
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "riscv64"
+)))]
+compile_error!("the `linux-raw` feature does not support this architecture");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_init_from_auxv() {
+        let page_size = ::get();
+        let auxv = [3, 64, 6, page_size, 0, 0];
+        unsafe { init_from_auxv(auxv.as_ptr()) };
+
+        assert_eq!(initial_auxv().map(PageSize::bytes), Ok(page_size));
+    }
+}
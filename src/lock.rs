@@ -0,0 +1,311 @@
+//! Locking pages in memory (`mlock`), within the `RLIMIT_MEMLOCK` budget.
+
+use core::ops::Range;
+
+use fs::{self, Path};
+use huge::parse_kib;
+use unix::sys;
+use {Align, Error, PageSize};
+
+/// The capability that exempts a process from the memlock limit.
+const CAP_IPC_LOCK: u32 = 14;
+
+/// The memory a process may lock, as limited by `RLIMIT_MEMLOCK`.
+///
+/// Processes with the `CAP_IPC_LOCK` capability are not limited.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate page_size;
+///
+/// let budget = page_size::MemlockBudget::read().unwrap();
+/// match budget.available() {
+///     Some(bytes) => println!("{} bytes left to lock", bytes),
+///     None => println!("no memlock limit"),
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemlockBudget {
+    /// The soft `RLIMIT_MEMLOCK` limit in bytes, or `None` if there is none.
+    pub limit: Option<u64>,
+    /// The number of bytes the process has locked (`VmLck` in
+    /// `/proc/self/status`).
+    pub locked: u64,
+    /// The size of the address space of the process (`VmSize` in
+    /// `/proc/self/status`), which [`lock_all`] checks against the limit.
+    pub mapped: u64,
+    /// Whether the process has the `CAP_IPC_LOCK` capability (in `CapEff` in
+    /// `/proc/self/status`), and so is not limited.
+    pub capable: bool,
+}
+
+impl MemlockBudget {
+    /// Reads the current budget of the process.
+    pub fn read() -> Result<MemlockBudget, Error> {
+        let limit = sys::memlock_limit().map_err(|errno| Error::Os {
+            operation: "getrlimit",
+            errno,
+        })?;
+
+        let (mut locked, mut mapped, mut capabilities) = (None, None, None);
+        fs::for_each_line(&Path::new("/proc/self/status"), |line| {
+            if line.starts_with(b"VmLck:") {
+                locked = parse_kib(&line[b"VmLck:".len()..]);
+            } else if line.starts_with(b"VmSize:") {
+                mapped = parse_kib(&line[b"VmSize:".len()..]);
+            } else if line.starts_with(b"CapEff:") {
+                capabilities = fs::parse_hex(&line[b"CapEff:".len()..]);
+            }
+            locked.is_none() || mapped.is_none() || capabilities.is_none()
+        })?;
+
+        let capabilities = capabilities.ok_or(Error::Malformed {
+            what: "CapEff in /proc/self/status",
+        })?;
+        let field = |value: Option<usize>, what| {
+            value
+                .map(|value| value as u64)
+                .ok_or(Error::Malformed { what })
+        };
+        Ok(MemlockBudget {
+            limit,
+            locked: field(locked, "VmLck in /proc/self/status")?,
+            mapped: field(mapped, "VmSize in /proc/self/status")?,
+            capable: capabilities & 1 << CAP_IPC_LOCK != 0,
+        })
+    }
+
+    /// Returns the number of bytes that can still be locked, or `None` if
+    /// the process is not limited.
+    pub fn available(&self) -> Option<u64> {
+        if self.capable {
+            return None;
+        }
+
+        self.limit.map(|limit| limit.saturating_sub(self.locked))
+    }
+
+    /// Returns the number of pages by which locking `bytes` more bytes would
+    /// exceed the limit, or 0 if they fit.
+    ///
+    /// Pages that are already locked count again, so this can overestimate.
+    pub fn excess_pages(&self, bytes: u64) -> usize {
+        self.excess(self.locked.saturating_add(bytes))
+    }
+
+    // Returns the number of pages by which `bytes` exceeds the limit.
+    fn excess(&self, bytes: u64) -> usize {
+        let page_size = PageSize::get();
+        let limit = match self.limit {
+            Some(limit) if !self.capable => limit,
+            _ => return 0,
+        };
+
+        // The kernel compares whole pages, rounding the limit down.
+        let pages = |bytes: u64| {
+            (bytes >> page_size.shift()) + (bytes & page_size.mask() as u64 != 0) as u64
+        };
+        pages(bytes).saturating_sub(limit >> page_size.shift()) as usize
+    }
+}
+
+/// Locks the pages of the address range `range` in memory, faulting them in.
+///
+/// `range` is rounded outward to page boundaries. Fails with an
+/// [`Error::MemlockLimit`] if the pages do not fit in the `RLIMIT_MEMLOCK`
+/// budget, an [`Error::OutOfRange`] if `range` is reversed, and with an
+/// [`Error::Os`] if part of it is not mapped (`ENOMEM`).
+///
+/// # Example
+///
+/// ```rust
+/// extern crate page_size;
+/// use page_size::PageMapping;
+///
+/// let mapping = PageMapping::new(1).unwrap();
+/// let range = mapping.as_ptr() as usize..mapping.as_ptr() as usize + mapping.len();
+/// match page_size::lock(range.clone()) {
+///     Ok(()) => page_size::unlock(range).unwrap(),
+///     Err(err) => println!("cannot lock: {}", err),
+/// }
+/// ```
+pub fn lock(range: Range<usize>) -> Result<(), Error> {
+    mlock(range, 0)
+}
+
+/// Locks the pages of the address range `range` in memory as they are
+/// faulted in, rather than all at once (`MLOCK_ONFAULT`, Linux 4.4).
+///
+/// See [`lock`].
+pub fn lock_on_fault(range: Range<usize>) -> Result<(), Error> {
+    mlock(range, sys::MLOCK_ONFAULT)
+}
+
+/// Unlocks the pages of the address range `range`, rounded outward to page
+/// boundaries.
+pub fn unlock(range: Range<usize>) -> Result<(), Error> {
+    let range = outward_page_range(range)?;
+    sys::munlock(range.start, range.end - range.start).map_err(|errno| Error::Os {
+        operation: "munlock",
+        errno,
+    })
+}
+
+/// Locks every page currently mapped by the process if `current` is set, and
+/// every page it maps later if `future` is set.
+///
+/// With `current`, fails with an [`Error::MemlockLimit`] if the address space
+/// of the process does not fit in the `RLIMIT_MEMLOCK` budget. With
+/// `future`, later mappings that would exceed it fail instead.
+pub fn lock_all(current: bool, future: bool) -> Result<(), Error> {
+    let flags =
+        if current { sys::MCL_CURRENT } else { 0 } | if future { sys::MCL_FUTURE } else { 0 };
+    match sys::mlockall(flags) {
+        Ok(()) => Ok(()),
+        Err(errno @ sys::ENOMEM) | Err(errno @ sys::EPERM) if current => Err(limit_error(
+            |budget| budget.excess(budget.mapped),
+            "mlockall",
+            errno,
+        )),
+        Err(errno) => Err(Error::Os {
+            operation: "mlockall",
+            errno,
+        }),
+    }
+}
+
+/// Unlocks every page of the process, and stops [`lock_all`] from locking
+/// future mappings.
+pub fn unlock_all() -> Result<(), Error> {
+    sys::munlockall().map_err(|errno| Error::Os {
+        operation: "munlockall",
+        errno,
+    })
+}
+
+// Locks `range` with `mlock2` flags `flags`. The budget is only checked once
+// the kernel has refused the range: pages that are already locked do not
+// count twice.
+fn mlock(range: Range<usize>, flags: i32) -> Result<(), Error> {
+    let range = outward_page_range(range)?;
+    let len = range.end - range.start;
+    let operation = if flags == 0 { "mlock" } else { "mlock2" };
+
+    match sys::mlock(range.start, len, flags) {
+        Ok(()) => Ok(()),
+        Err(errno @ sys::ENOMEM) | Err(errno @ sys::EAGAIN) | Err(errno @ sys::EPERM) => Err(
+            limit_error(|budget| budget.excess_pages(len as u64), operation, errno),
+        ),
+        Err(errno) => Err(Error::Os { operation, errno }),
+    }
+}
+
+// Reports a failure with `errno`, which the kernel also uses for unmapped
+// pages, as an `Error::MemlockLimit` if `excess` finds pages over the budget.
+// If the budget cannot be read (without procfs, for one), the failure is
+// reported as is.
+fn limit_error<F>(excess: F, operation: &'static str, errno: i32) -> Error
+where
+    F: FnOnce(&MemlockBudget) -> usize,
+{
+    match MemlockBudget::read().map(|budget| excess(&budget)) {
+        Ok(0) | Err(_) => Error::Os { operation, errno },
+        Ok(excess_pages) => Error::MemlockLimit { excess_pages },
+    }
+}
+
+/// Rounds the address range `range` outward to page boundaries, like the
+/// kernel does for `mlock`.
+fn outward_page_range(range: Range<usize>) -> Result<Range<usize>, Error> {
+    let page_size = PageSize::get();
+    match range.end.page_align_up(page_size) {
+        Some(end) if range.start <= range.end => Ok(range.start.page_align_down(page_size)..end),
+        _ => Err(Error::OutOfRange {
+            start: range.start,
+            end: range.end,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use PageMapping;
+
+    #[test]
+    fn test_excess_pages() {
+        let page_size = ::get() as u64;
+        let budget = MemlockBudget {
+            limit: Some(8 * page_size + 1),
+            locked: 2 * page_size,
+            mapped: 100 * page_size,
+            capable: false,
+        };
+        assert_eq!(budget.available(), Some(6 * page_size + 1));
+        assert_eq!(budget.excess_pages(6 * page_size), 0);
+        assert_eq!(budget.excess_pages(6 * page_size + 1), 1);
+        assert_eq!(budget.excess_pages(10 * page_size), 4);
+        assert_eq!(budget.excess(budget.mapped), 92);
+
+        let unlimited = MemlockBudget {
+            limit: None,
+            ..budget
+        };
+        assert_eq!(unlimited.excess_pages(u64::MAX), 0);
+
+        let capable = MemlockBudget {
+            capable: true,
+            ..budget
+        };
+        assert_eq!(capable.available(), None);
+        assert_eq!(capable.excess_pages(u64::MAX), 0);
+        assert_eq!(capable.excess(capable.mapped), 0);
+    }
+
+    #[test]
+    fn test_lock() {
+        let budget = MemlockBudget::read().unwrap();
+        assert!(budget.mapped > 0);
+
+        let mapping = PageMapping::pages(2).unwrap();
+        let start = mapping.as_ptr() as usize;
+        let range = start + 1..start + ::get() + 1;
+        match lock(range.clone()) {
+            Ok(()) => unlock(range).unwrap(),
+            // Unprivileged processes may have a small limit.
+            Err(Error::MemlockLimit { excess_pages }) => assert!(excess_pages > 0),
+            Err(err) => panic!("{}", err),
+        }
+        match lock_on_fault(start..start + 1) {
+            Ok(()) | Err(Error::MemlockLimit { .. }) => {}
+            Err(err) => panic!("{}", err),
+        }
+
+        // Unmapped pages are only blamed on the limit when over budget.
+        let page_size = ::get();
+        let unmapped = {
+            let mapping = PageMapping::pages(1).unwrap();
+            mapping.as_ptr() as usize
+        };
+        let expected = match MemlockBudget::read()
+            .unwrap()
+            .excess_pages(page_size as u64)
+        {
+            0 => Error::Os {
+                operation: "mlock",
+                errno: sys::ENOMEM,
+            },
+            excess_pages => Error::MemlockLimit { excess_pages },
+        };
+        assert_eq!(lock(unmapped..unmapped + page_size), Err(expected));
+
+        assert_eq!(
+            unlock(start + 1..start),
+            Err(Error::OutOfRange {
+                start: start + 1,
+                end: start
+            })
+        );
+    }
+}
@@ -0,0 +1,503 @@
+//! Anonymous memory mappings made of whole pages.
+
+use core::ops::{Deref, DerefMut, Range};
+use core::ptr::{self, NonNull};
+use core::{fmt, slice};
+
+use huge::for_each_huge_page_size;
+use thp::{self, Mode};
+use unix::sys;
+use {Align, Error, PageSize};
+
+/// A private, anonymous, read-write memory mapping of whole pages, unmapped
+/// on drop.
+///
+/// The memory is zero-filled and page-aligned. It dereferences to `[u8]`.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate page_size;
+/// use page_size::PageMapping;
+///
+/// let mut mapping = PageMapping::new(100).unwrap();
+/// assert_eq!(mapping.len(), page_size::get());
+/// assert!(mapping.iter().all(|&byte| byte == 0));
+/// mapping[0] = 1;
+/// ```
+pub struct PageMapping {
+    ptr: NonNull<u8>,
+    len: usize,
+    page_size: PageSize,
+    backing: Backing,
+}
+
+/// The kind of pages backing a [`PageMapping`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Backing {
+    /// Huge pages from the hugetlb pool, mapped with `MAP_HUGETLB`.
+    HugeTlb,
+    /// Base pages, aligned to the THP size and advised with `MADV_HUGEPAGE`,
+    /// so that the kernel backs them with transparent huge pages when it
+    /// can.
+    TransparentHuge,
+    /// Base pages.
+    Base,
+}
+
+// A `PageMapping` owns its memory like a `Box<[u8]>` does.
+unsafe impl Send for PageMapping {}
+unsafe impl Sync for PageMapping {}
+
+impl PageMapping {
+    /// Maps at least `len` bytes, rounded up to a whole number of pages of
+    /// the system's page size.
+    ///
+    /// Fails with an [`Error::Os`] if `len` is 0 or the kernel refuses the
+    /// mapping.
+    pub fn new(len: usize) -> Result<PageMapping, Error> {
+        let page_size = PageSize::get();
+        PageMapping::pages(page_size.pages_needed(len))
+    }
+
+    /// Maps `count` pages of the system's page size.
+    ///
+    /// Fails with an [`Error::Os`] if `count` is 0 or the kernel refuses the
+    /// mapping.
+    pub fn pages(count: usize) -> Result<PageMapping, Error> {
+        let page_size = PageSize::get();
+        if count == 0 {
+            return Err(Error::Os {
+                operation: "mmap",
+                errno: sys::EINVAL,
+            });
+        }
+        let len = page_size.checked_mul(count).ok_or(Error::Os {
+            operation: "mmap",
+            errno: sys::ENOMEM,
+        })?;
+
+        unsafe {
+            PageMapping::map(
+                len,
+                sys::PROT_READ | sys::PROT_WRITE,
+                sys::MAP_PRIVATE | sys::MAP_ANONYMOUS,
+                page_size,
+                Backing::Base,
+            )
+        }
+    }
+
+    /// Maps at least `len` bytes backed by huge pages of `size` if possible,
+    /// falling back to smaller pages if not.
+    ///
+    /// The backing is chosen in order from:
+    ///
+    /// 1. Huge pages of `size` from the hugetlb pool, if the kernel supports
+    ///    that size and the pool has enough pages. `len` is rounded up to a
+    ///    multiple of `size`.
+    /// 2. Transparent huge pages, if the kernel supports them and they are
+    ///    not disabled: the mapping is aligned to the THP size, `len` is
+    ///    rounded up to a multiple of it, and the mapping is advised with
+    ///    `MADV_HUGEPAGE`. The kernel still falls back to base pages when it
+    ///    has no huge page at hand.
+    /// 3. Base pages.
+    ///
+    /// [`backing`](PageMapping::backing) tells which one was obtained, and
+    /// [`page_size`](PageMapping::page_size) is `size` only for the first.
+    /// Fails with an [`Error::Os`] if `len` is 0 or the kernel refuses even
+    /// the base page mapping.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate page_size;
+    /// use page_size::{Backing, PageMapping, PageSize};
+    ///
+    /// let size = PageSize::new(2 << 20).unwrap();
+    /// let mapping = PageMapping::huge(4 << 20, size).unwrap();
+    /// match mapping.backing() {
+    ///     Backing::HugeTlb => println!("2 MiB hugetlb pages"),
+    ///     Backing::TransparentHuge => println!("transparent huge pages"),
+    ///     _ => println!("base pages"),
+    /// }
+    /// ```
+    pub fn huge(len: usize, size: PageSize) -> Result<PageMapping, Error> {
+        if len == 0 {
+            return Err(Error::Os {
+                operation: "mmap",
+                errno: sys::EINVAL,
+            });
+        }
+
+        // Errors reading sysfs just rule out a tier.
+        let mut supported = false;
+        for_each_huge_page_size(|huge_size| supported |= huge_size == size).ok();
+        if let (true, Some(len)) = (supported, size.checked_mul(size.pages_needed(len))) {
+            let flags = sys::MAP_PRIVATE
+                | sys::MAP_ANONYMOUS
+                | sys::MAP_HUGETLB
+                | (size.shift() as i32) << sys::MAP_HUGE_SHIFT;
+            let mapping = unsafe {
+                PageMapping::map(
+                    len,
+                    sys::PROT_READ | sys::PROT_WRITE,
+                    flags,
+                    size,
+                    Backing::HugeTlb,
+                )
+            };
+            // Typically `ENOMEM`, when the pool has too few pages.
+            if let Ok(mapping) = mapping {
+                return Ok(mapping);
+            }
+        }
+
+        let thp_size = match (thp::mode(), thp::pmd_size()) {
+            (Ok(Mode::Never), _) => None,
+            (Ok(_), Ok(thp_size)) => thp_size,
+            _ => None,
+        };
+        if let Some(thp_size) = thp_size {
+            let mapping = thp_size
+                .checked_mul(thp_size.pages_needed(len))
+                .map(|len| map_aligned(len, thp_size));
+            if let Some(Ok(mut mapping)) = mapping {
+                // Without the advice, the mapping is still good for base
+                // pages.
+                let advice =
+                    unsafe { sys::madvise(mapping.ptr.as_ptr(), mapping.len, sys::MADV_HUGEPAGE) };
+                if advice.is_ok() {
+                    mapping.backing = Backing::TransparentHuge;
+                }
+                return Ok(mapping);
+            }
+        }
+
+        PageMapping::new(len)
+    }
+
+    /// Maps `len` bytes of anonymous memory.
+    ///
+    /// # Safety
+    ///
+    /// `len` must be a multiple of `page_size`, and `flags` must make the
+    /// kernel map pages of `page_size` at a fresh address.
+    pub(crate) unsafe fn map(
+        len: usize,
+        prot: i32,
+        flags: i32,
+        page_size: PageSize,
+        backing: Backing,
+    ) -> Result<PageMapping, Error> {
+        let ptr =
+            sys::mmap(ptr::null_mut(), len, prot, flags, -1, 0).map_err(|errno| Error::Os {
+                operation: "mmap",
+                errno,
+            })?;
+
+        Ok(PageMapping {
+            ptr: NonNull::new_unchecked(ptr),
+            len,
+            page_size,
+            backing,
+        })
+    }
+
+    /// Returns the size of the pages backing the mapping.
+    #[inline]
+    pub fn page_size(&self) -> PageSize {
+        self.page_size
+    }
+
+    /// Returns the kind of pages backing the mapping.
+    #[inline]
+    pub fn backing(&self) -> Backing {
+        self.backing
+    }
+}
+
+impl Deref for PageMapping {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl DerefMut for PageMapping {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl AsRef<[u8]> for PageMapping {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl AsMut<[u8]> for PageMapping {
+    #[inline]
+    fn as_mut(&mut self) -> &mut [u8] {
+        self
+    }
+}
+
+impl fmt::Debug for PageMapping {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PageMapping")
+            .field("ptr", &self.ptr)
+            .field("len", &self.len)
+            .field("page_size", &self.page_size)
+            .field("backing", &self.backing)
+            .finish()
+    }
+}
+
+impl Drop for PageMapping {
+    fn drop(&mut self) {
+        let result = unsafe { sys::munmap(self.ptr.as_ptr(), self.len) };
+        debug_assert!(result.is_ok(), "munmap failed: {:?}", result);
+    }
+}
+
+/// Maps at least `len` bytes of private, anonymous, read-write memory,
+/// aligned to `align` rather than just to the page size.
+///
+/// `len` is rounded up to a whole number of pages. Alignments no larger than
+/// the page size give a plain [`PageMapping`]. For larger ones, such as the
+/// 2 MiB [`pmd_size`](::thp::pmd_size) that transparent huge pages need,
+/// the kernel is asked for enough extra bytes to hold an aligned range, and
+/// the excess on either side of it is unmapped again, so no address space is
+/// left behind.
+///
+/// Fails with an [`Error::Os`] if `len` is 0 or the kernel refuses the
+/// mapping.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate page_size;
+/// use page_size::PageSize;
+///
+/// let align = PageSize::new(2 << 20).unwrap();
+/// let mapping = page_size::map_aligned(4 << 20, align).unwrap();
+/// assert!(align.is_aligned(mapping.as_ptr()));
+/// ```
+pub fn map_aligned(len: usize, align: PageSize) -> Result<PageMapping, Error> {
+    let page_size = PageSize::get();
+    if len == 0 {
+        return Err(Error::Os {
+            operation: "mmap",
+            errno: sys::EINVAL,
+        });
+    }
+    let too_large = Error::Os {
+        operation: "mmap",
+        errno: sys::ENOMEM,
+    };
+    let len = page_size
+        .checked_mul(page_size.pages_needed(len))
+        .ok_or(too_large)?;
+
+    // The kernel only aligns mappings to the page size, so over-map by
+    // `align` minus the page size to be sure to hold an aligned range.
+    let slack = align.bytes().saturating_sub(page_size.bytes());
+    let total = len.checked_add(slack).ok_or(too_large)?;
+    let mut mapping = unsafe {
+        PageMapping::map(
+            total,
+            sys::PROT_READ | sys::PROT_WRITE,
+            sys::MAP_PRIVATE | sys::MAP_ANONYMOUS,
+            page_size,
+            Backing::Base,
+        )
+    }?;
+
+    // Shrink the mapping in place, so that dropping it on error unmaps
+    // whatever is left.
+    let base = mapping.ptr.as_ptr() as usize;
+    let start = base
+        .page_align_up(align)
+        .expect("the mapping holds an aligned address");
+    let (head, tail) = (start - base, total - len - (start - base));
+    let unmap = |addr: usize, len: usize| match len {
+        0 => Ok(()),
+        len => unsafe { sys::munmap(addr as *mut u8, len) }.map_err(|errno| Error::Os {
+            operation: "munmap",
+            errno,
+        }),
+    };
+    unmap(start + len, tail)?;
+    mapping.len = total - tail;
+    unmap(base, head)?;
+    mapping.ptr = unsafe { NonNull::new_unchecked(start as *mut u8) };
+    mapping.len = len;
+
+    Ok(mapping)
+}
+
+/// Checks that the address range `range` starts on a page boundary and
+/// rounds its end up to one, like the kernel does for `mprotect` and
+/// `madvise`.
+pub fn page_range(range: Range<usize>) -> Result<Range<usize>, Error> {
+    let page_size = PageSize::get();
+    if !range.start.is_page_aligned(page_size) {
+        return Err(Error::Unaligned { value: range.start });
+    }
+    match range.end.page_align_up(page_size) {
+        Some(end) if range.start <= range.end => Ok(range.start..end),
+        _ => Err(Error::OutOfRange {
+            start: range.start,
+            end: range.end,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_mapping() {
+        let page_size = ::get();
+
+        let mut mapping = PageMapping::new(page_size + 1).unwrap();
+        assert_eq!(mapping.len(), 2 * page_size);
+        assert_eq!(mapping.as_ptr() as usize % page_size, 0);
+        assert!(mapping.iter().all(|&byte| byte == 0));
+        for byte in mapping.iter_mut() {
+            *byte = 0xa5;
+        }
+        assert!(mapping.iter().all(|&byte| byte == 0xa5));
+
+        assert_eq!(PageMapping::pages(3).unwrap().len(), 3 * page_size);
+        assert_eq!(
+            PageMapping::new(0).err(),
+            Some(Error::Os {
+                operation: "mmap",
+                errno: sys::EINVAL
+            })
+        );
+        assert_eq!(
+            PageMapping::pages(usize::MAX).err(),
+            Some(Error::Os {
+                operation: "mmap",
+                errno: sys::ENOMEM
+            })
+        );
+    }
+
+    #[test]
+    fn test_huge() {
+        let page_size = PageSize::get();
+
+        // The base page size is never a hugetlb size.
+        let mut mapping = PageMapping::huge(page_size.bytes() + 1, page_size).unwrap();
+        assert_ne!(mapping.backing(), Backing::HugeTlb);
+        assert_eq!(mapping.page_size(), page_size);
+        if mapping.backing() == Backing::TransparentHuge {
+            let thp_size = thp::pmd_size().unwrap().unwrap();
+            assert_eq!(mapping.len(), thp_size.bytes());
+            assert!(thp_size.is_aligned(mapping.as_ptr()));
+        }
+        let len = mapping.len();
+        mapping[len - 1] = 1;
+
+        if let Some(size) = ::default_huge_page_size().unwrap() {
+            let pool = ::HugePagePool::read(size).unwrap();
+            let mapping = PageMapping::huge(size.bytes(), size).unwrap();
+            if mapping.backing() == Backing::HugeTlb {
+                assert_eq!(mapping.page_size(), size);
+                assert_eq!(mapping.len(), size.bytes());
+            }
+            // Without pages in the pool, the mapping falls back.
+            if pool.available() == 0 && pool.nr_overcommit_hugepages == Some(0) {
+                assert_ne!(mapping.backing(), Backing::HugeTlb);
+            }
+        }
+
+        assert_eq!(
+            PageMapping::huge(0, page_size).err(),
+            Some(Error::Os {
+                operation: "mmap",
+                errno: sys::EINVAL
+            })
+        );
+    }
+
+    #[test]
+    fn test_map_aligned() {
+        let page_size = PageSize::get();
+
+        for &shift in &[page_size.shift() - 1, 21, 30] {
+            let align = PageSize::from_shift(shift).unwrap();
+            let mut mapping = map_aligned(page_size.bytes() + 1, align).unwrap();
+            assert_eq!(mapping.len(), 2 * page_size.bytes());
+            assert!(align.is_aligned(mapping.as_ptr()));
+            assert!(page_size.is_aligned(mapping.as_ptr()));
+            mapping[0] = 1;
+            mapping[2 * page_size.bytes() - 1] = 1;
+        }
+
+        assert_eq!(
+            map_aligned(0, PageSize::from_shift(21).unwrap()).err(),
+            Some(Error::Os {
+                operation: "mmap",
+                errno: sys::EINVAL
+            })
+        );
+        assert_eq!(
+            map_aligned(usize::MAX, page_size).err(),
+            Some(Error::Os {
+                operation: "mmap",
+                errno: sys::ENOMEM
+            })
+        );
+    }
+
+    #[test]
+    fn test_map_aligned_unmaps_slack() {
+        let page_size = ::get();
+        // The kernel may align large mappings to the THP size by itself, but
+        // not to 1 GiB, so there is slack to unmap on both sides.
+        let align = PageSize::from_shift(30).unwrap();
+
+        // `mincore` fails with `ENOMEM` for unmapped pages.
+        let unmapped = |addr: usize| {
+            let mut vec = [0u8];
+            let result = unsafe { sys::mincore(addr as *mut u8, page_size, &mut vec) };
+            result == Err(sys::ENOMEM)
+        };
+        let mapping = map_aligned(page_size, align).unwrap();
+        let start = mapping.as_ptr() as usize;
+        let end = start + mapping.len();
+        assert!(!unmapped(start));
+        // Had the slack been left mapped, neither neighbour would be
+        // unmapped. Other mappings may take the place of one of them.
+        assert!(unmapped(start - page_size) || unmapped(end));
+    }
+
+    #[test]
+    fn test_page_range() {
+        let page_size = ::get();
+        assert_eq!(
+            page_range(page_size..page_size + 1),
+            Ok(page_size..2 * page_size)
+        );
+        assert_eq!(page_range(0..0), Ok(0..0));
+        assert_eq!(page_range(1..page_size), Err(Error::Unaligned { value: 1 }));
+        assert_eq!(
+            page_range(page_size..usize::MAX),
+            Err(Error::OutOfRange {
+                start: page_size,
+                end: usize::MAX
+            })
+        );
+    }
+}
@@ -0,0 +1,159 @@
+//! Changing the protection of pages.
+
+use core::fmt;
+use core::ops::{BitOr, BitOrAssign, Range};
+
+use mapping::page_range;
+use unix::sys;
+use Error;
+
+/// The access allowed to a range of pages: any combination of read, write
+/// and execute, or none.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate page_size;
+/// use page_size::Protection;
+///
+/// assert_eq!(Protection::READ | Protection::WRITE, Protection::READ_WRITE);
+/// assert!(Protection::READ_EXEC.contains(Protection::EXEC));
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Protection(i32);
+
+impl Protection {
+    /// No access: any access faults.
+    pub const NONE: Protection = Protection(sys::PROT_NONE);
+    /// Read access.
+    pub const READ: Protection = Protection(sys::PROT_READ);
+    /// Write access. On most architectures, this implies read access.
+    pub const WRITE: Protection = Protection(sys::PROT_WRITE);
+    /// Execute access.
+    pub const EXEC: Protection = Protection(sys::PROT_EXEC);
+    /// Read and write access.
+    pub const READ_WRITE: Protection = Protection(sys::PROT_READ | sys::PROT_WRITE);
+    /// Read and execute access.
+    pub const READ_EXEC: Protection = Protection(sys::PROT_READ | sys::PROT_EXEC);
+
+    /// Returns `true` if `self` allows every access `other` allows.
+    #[inline]
+    pub fn contains(self, other: Protection) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for Protection {
+    type Output = Protection;
+
+    #[inline]
+    fn bitor(self, other: Protection) -> Protection {
+        Protection(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for Protection {
+    #[inline]
+    fn bitor_assign(&mut self, other: Protection) {
+        self.0 |= other.0;
+    }
+}
+
+impl fmt::Debug for Protection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flags = [
+            (Protection::READ, 'r'),
+            (Protection::WRITE, 'w'),
+            (Protection::EXEC, 'x'),
+        ];
+        for &(flag, letter) in &flags {
+            let letter = if self.contains(flag) { letter } else { '-' };
+            fmt::Write::write_char(f, letter)?;
+        }
+        Ok(())
+    }
+}
+
+/// Sets the protection of the pages of the address range `range`.
+///
+/// `range.start` must lie on a page boundary; `range.end` is rounded up to
+/// the next one. Fails with an [`Error::Unaligned`] or
+/// [`Error::OutOfRange`] on invalid ranges, and with an [`Error::Os`] if the
+/// kernel refuses the change, for example because part of the range is not
+/// mapped (`ENOMEM`) or a writable and executable mapping is not allowed
+/// (`EACCES`).
+///
+/// # Safety
+///
+/// Revoking access to memory that is still in use makes the next access
+/// fault, and allowing writes to memory the program assumes immutable breaks
+/// its invariants. The caller must own the pages of `range`.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate page_size;
+/// use page_size::{PageMapping, Protection};
+///
+/// let mapping = PageMapping::new(1).unwrap();
+/// let start = mapping.as_ptr() as usize;
+/// unsafe { page_size::protect(start..start + 1, Protection::READ).unwrap() };
+/// assert_eq!(mapping[0], 0);
+/// ```
+pub unsafe fn protect(range: Range<usize>, protection: Protection) -> Result<(), Error> {
+    let range = page_range(range)?;
+    sys::mprotect(
+        range.start as *mut u8,
+        range.end - range.start,
+        protection.0,
+    )
+    .map_err(|errno| Error::Os {
+        operation: "mprotect",
+        errno,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use PageMapping;
+
+    #[test]
+    fn test_protect() {
+        let page_size = ::get();
+        let mut mapping = PageMapping::pages(2).unwrap();
+        let start = mapping.as_ptr() as usize;
+
+        unsafe {
+            protect(start..start + page_size, Protection::NONE).unwrap();
+            protect(start..start + 1, Protection::READ).unwrap();
+            protect(start..start + 2 * page_size, Protection::READ_WRITE).unwrap();
+        }
+        mapping[2 * page_size - 1] = 1;
+
+        assert_eq!(
+            unsafe { protect(start + 1..start + 2, Protection::READ) },
+            Err(Error::Unaligned { value: start + 1 })
+        );
+        assert_eq!(
+            unsafe { protect(start..start - 1, Protection::READ) },
+            Err(Error::OutOfRange {
+                start,
+                end: start - 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_protection() {
+        let mut protection = Protection::NONE;
+        protection |= Protection::READ;
+        assert_eq!(protection, Protection::READ);
+        assert_eq!(
+            Protection::READ_EXEC | Protection::WRITE,
+            Protection(sys::PROT_READ | sys::PROT_WRITE | sys::PROT_EXEC)
+        );
+        assert!(Protection::READ_WRITE.contains(Protection::NONE));
+        assert!(!Protection::READ.contains(Protection::WRITE));
+    }
+}
@@ -0,0 +1,212 @@
+//! Reserving address space and committing memory in it separately.
+
+use core::ops::Range;
+use core::{mem, ptr};
+
+use unix::sys;
+use {Align, Error, PageSize};
+
+/// A range of reserved address space, whose pages are committed and
+/// decommitted individually.
+///
+/// This is the reserve-then-commit model of `VirtualAlloc` on Windows.
+/// Reserved pages are mapped inaccessible, so they cost neither memory nor
+/// commit charge; committing makes them readable and writable and charges
+/// them against the system's commit limit, and decommitting returns them to
+/// the kernel. The region is released when it is dropped.
+///
+/// Ranges passed to [`commit`](VirtualRegion::commit) and
+/// [`decommit`](VirtualRegion::decommit) are byte offsets from the start of
+/// the region, whose bounds must be multiples of the page size.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate page_size;
+/// use page_size::VirtualRegion;
+///
+/// let page_size = page_size::get();
+/// let mut region = VirtualRegion::reserve(1 << 30).unwrap();
+/// region.commit(0..page_size).unwrap();
+/// unsafe { *region.as_ptr() = 1 };
+/// region.decommit(0..page_size).unwrap();
+/// region.release().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct VirtualRegion {
+    ptr: *mut u8,
+    len: usize,
+}
+
+// A `VirtualRegion` owns its address space; accessing the memory in it
+// already requires `unsafe`.
+unsafe impl Send for VirtualRegion {}
+unsafe impl Sync for VirtualRegion {}
+
+impl VirtualRegion {
+    /// Reserves at least `bytes` bytes of address space, rounded up to a
+    /// multiple of the system's allocation granularity.
+    ///
+    /// Fails with an [`Error::Os`] if `bytes` is 0 or the kernel refuses the
+    /// reservation.
+    pub fn reserve(bytes: usize) -> Result<VirtualRegion, Error> {
+        let len = ::align_up_granularity(bytes).ok_or(Error::Os {
+            operation: "mmap",
+            errno: sys::ENOMEM,
+        })?;
+        let ptr = unsafe {
+            sys::mmap(
+                ptr::null_mut(),
+                len,
+                sys::PROT_NONE,
+                sys::MAP_PRIVATE | sys::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        }
+        .map_err(|errno| Error::Os {
+            operation: "mmap",
+            errno,
+        })?;
+
+        Ok(VirtualRegion { ptr, len })
+    }
+
+    /// Returns a pointer to the start of the region.
+    #[inline]
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.ptr
+    }
+
+    /// Returns the size of the region in bytes.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the region is empty. Since reservations cannot be
+    /// empty, this is always `false`.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Commits the pages of `range`, making them readable and writable.
+    ///
+    /// Newly committed pages are zero-filled; pages that were already
+    /// committed keep their contents.
+    pub fn commit(&mut self, range: Range<usize>) -> Result<(), Error> {
+        let (ptr, len) = self.pages(range)?;
+        unsafe { sys::mprotect(ptr, len, sys::PROT_READ | sys::PROT_WRITE) }.map_err(|errno| {
+            Error::Os {
+                operation: "mprotect",
+                errno,
+            }
+        })
+    }
+
+    /// Decommits the pages of `range`, discarding their contents and
+    /// returning their memory and commit charge to the kernel. The pages
+    /// stay reserved.
+    pub fn decommit(&mut self, range: Range<usize>) -> Result<(), Error> {
+        let (ptr, len) = self.pages(range)?;
+        if len == 0 {
+            return Ok(());
+        }
+        // Mapping fresh inaccessible pages over the range frees the old ones
+        // and their commit charge at once.
+        unsafe {
+            sys::mmap(
+                ptr,
+                len,
+                sys::PROT_NONE,
+                sys::MAP_PRIVATE | sys::MAP_ANONYMOUS | sys::MAP_FIXED,
+                -1,
+                0,
+            )
+        }
+        .map(drop)
+        .map_err(|errno| Error::Os {
+            operation: "mmap",
+            errno,
+        })
+    }
+
+    /// Releases the region, reporting failure unlike dropping it.
+    pub fn release(self) -> Result<(), Error> {
+        let result = unsafe { sys::munmap(self.ptr, self.len) };
+        mem::forget(self);
+        result.map_err(|errno| Error::Os {
+            operation: "munmap",
+            errno,
+        })
+    }
+
+    // Checks that `range` is a page-aligned subrange of the region, returning
+    // its address and length.
+    fn pages(&self, range: Range<usize>) -> Result<(*mut u8, usize), Error> {
+        let page_size = PageSize::get();
+        if range.start > range.end || range.end > self.len {
+            return Err(Error::OutOfRange {
+                start: range.start,
+                end: range.end,
+            });
+        }
+        for &value in &[range.start, range.end] {
+            if !value.is_page_aligned(page_size) {
+                return Err(Error::Unaligned { value });
+            }
+        }
+
+        Ok((self.ptr.wrapping_add(range.start), range.end - range.start))
+    }
+}
+
+impl Drop for VirtualRegion {
+    fn drop(&mut self) {
+        let result = unsafe { sys::munmap(self.ptr, self.len) };
+        debug_assert!(result.is_ok(), "munmap failed: {:?}", result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_virtual_region() {
+        let page_size = ::get();
+        let mut region = VirtualRegion::reserve(4 * page_size - 1).unwrap();
+        assert_eq!(region.len(), 4 * page_size);
+        assert!(::is_aligned(region.as_ptr()));
+
+        region.commit(page_size..3 * page_size).unwrap();
+        unsafe {
+            let page = region.as_ptr().add(page_size);
+            assert_eq!(*page, 0);
+            *page = 1;
+            *page.add(2 * page_size - 1) = 1;
+        }
+        region.decommit(page_size..2 * page_size).unwrap();
+        region.commit(page_size..2 * page_size).unwrap();
+        unsafe { assert_eq!(*region.as_ptr().add(page_size), 0) };
+        // Empty ranges are fine.
+        region.commit(0..0).unwrap();
+        region.decommit(0..0).unwrap();
+
+        assert_eq!(
+            region.commit(1..page_size),
+            Err(Error::Unaligned { value: 1 })
+        );
+        assert_eq!(
+            region.commit(0..5 * page_size),
+            Err(Error::OutOfRange {
+                start: 0,
+                end: 5 * page_size
+            })
+        );
+        region.release().unwrap();
+
+        assert!(VirtualRegion::reserve(0).is_err());
+    }
+}
@@ -0,0 +1,223 @@
+//! Which pages of a range are resident in memory (`mincore`).
+
+#[cfg(any(not(feature = "no_std"), feature = "alloc"))]
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use mapping::page_range;
+use unix::sys;
+use {Error, PageSize};
+
+/// Calls `f` with the residency of each page of the address range `range`,
+/// in order: `true` if the page is resident in memory.
+///
+/// `range.start` must lie on a page boundary; `range.end` is rounded up to
+/// the next one. Fails with an [`Error::Unaligned`] or
+/// [`Error::OutOfRange`] on invalid ranges, and with an [`Error::Os`] if part
+/// of the range is not mapped (`ENOMEM`).
+///
+/// This is the allocation-free version of `residency`.
+pub fn for_each_page_residency<F>(range: Range<usize>, mut f: F) -> Result<(), Error>
+where
+    F: FnMut(bool),
+{
+    let range = page_range(range)?;
+    let page_size = PageSize::get();
+
+    let mut vec = [0u8; 256];
+    let mut start = range.start;
+    while start < range.end {
+        let pages = page_size.pages_needed(range.end - start).min(vec.len());
+        let len = pages << page_size.shift();
+        unsafe { sys::mincore(start as *mut u8, len, &mut vec[..pages]) }.map_err(|errno| {
+            Error::Os {
+                operation: "mincore",
+                errno,
+            }
+        })?;
+
+        // Only the lowest bit is defined.
+        for &page in &vec[..pages] {
+            f(page & 1 != 0);
+        }
+        start += len;
+    }
+
+    Ok(())
+}
+
+/// Returns which pages of the address range `range` are resident in memory.
+///
+/// See [`for_each_page_residency`] for the requirements on `range`.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate page_size;
+/// use page_size::PageMapping;
+///
+/// let mut mapping = PageMapping::pages(4).unwrap();
+/// mapping[0] = 1;
+/// let start = mapping.as_ptr() as usize;
+/// let residency = page_size::residency(start..start + mapping.len()).unwrap();
+/// assert_eq!(residency.pages(), 4);
+/// assert!(residency.is_resident(0));
+/// ```
+#[cfg(any(not(feature = "no_std"), feature = "alloc"))]
+pub fn residency(range: Range<usize>) -> Result<Residency, Error> {
+    let mut pages = Vec::new();
+    for_each_page_residency(range, |resident| pages.push(resident))?;
+
+    Ok(Residency::new(pages))
+}
+
+/// The residency of each page of a range, as returned by [`residency`] and
+/// [`file_residency`](::file_residency).
+///
+/// Pages are identified by their index from the start of the range.
+#[cfg(any(not(feature = "no_std"), feature = "alloc"))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Residency {
+    pages: Vec<bool>,
+}
+
+#[cfg(any(not(feature = "no_std"), feature = "alloc"))]
+impl Residency {
+    pub(crate) fn new(pages: Vec<bool>) -> Residency {
+        Residency { pages }
+    }
+
+    /// Returns the number of pages in the range.
+    #[inline]
+    pub fn pages(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Returns the number of resident pages.
+    pub fn resident_pages(&self) -> usize {
+        self.pages.iter().filter(|&&resident| resident).count()
+    }
+
+    /// Returns `true` if page `index` is resident.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of range.
+    #[inline]
+    pub fn is_resident(&self, index: usize) -> bool {
+        self.pages[index]
+    }
+
+    /// Returns the residency of every page, in order.
+    #[inline]
+    pub fn as_slice(&self) -> &[bool] {
+        &self.pages
+    }
+
+    /// Returns an iterator over the runs of consecutive resident or
+    /// non-resident pages, as ranges of page indices.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate page_size;
+    /// use page_size::PageMapping;
+    ///
+    /// let mut mapping = PageMapping::pages(4).unwrap();
+    /// mapping[page_size::get()] = 1;
+    /// let start = mapping.as_ptr() as usize;
+    /// for (pages, resident) in page_size::residency(start..start + mapping.len()).unwrap().runs() {
+    ///     println!("pages {:?}: {}", pages, if resident { "resident" } else { "not resident" });
+    /// }
+    /// ```
+    #[inline]
+    pub fn runs(&self) -> Runs<'_> {
+        Runs {
+            pages: &self.pages,
+            start: 0,
+        }
+    }
+}
+
+/// An iterator over runs of pages with the same residency, as returned by
+/// [`Residency::runs`].
+#[cfg(any(not(feature = "no_std"), feature = "alloc"))]
+#[derive(Debug, Clone)]
+pub struct Runs<'a> {
+    pages: &'a [bool],
+    start: usize,
+}
+
+#[cfg(any(not(feature = "no_std"), feature = "alloc"))]
+impl<'a> Iterator for Runs<'a> {
+    type Item = (Range<usize>, bool);
+
+    fn next(&mut self) -> Option<(Range<usize>, bool)> {
+        let resident = *self.pages.get(self.start)?;
+        let len = self.pages[self.start..]
+            .iter()
+            .position(|&page| page != resident)
+            .unwrap_or(self.pages.len() - self.start);
+
+        let run = self.start..self.start + len;
+        self.start = run.end;
+        Some((run, resident))
+    }
+}
+
+#[cfg(any(not(feature = "no_std"), feature = "alloc"))]
+impl<'a> ::core::iter::FusedIterator for Runs<'a> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use PageMapping;
+
+    #[test]
+    fn test_for_each_page_residency() {
+        let page_size = ::get();
+        // More pages than fit in one `mincore` call.
+        let mut mapping = PageMapping::pages(300).unwrap();
+        mapping[299 * page_size] = 1;
+        let start = mapping.as_ptr() as usize;
+
+        let (mut pages, mut last) = (0, false);
+        for_each_page_residency(start..start + mapping.len(), |resident| {
+            pages += 1;
+            last = resident;
+        })
+        .unwrap();
+        assert_eq!((pages, last), (300, true));
+
+        assert_eq!(
+            for_each_page_residency(start + 1..start + 2, |_| {}),
+            Err(Error::Unaligned { value: start + 1 })
+        );
+        assert_eq!(
+            for_each_page_residency(0..page_size, |_| {}),
+            Err(Error::Os {
+                operation: "mincore",
+                errno: sys::ENOMEM
+            })
+        );
+    }
+
+    #[cfg(any(not(feature = "no_std"), feature = "alloc"))]
+    #[test]
+    fn test_residency() {
+        let page_size = ::get();
+        let mut mapping = PageMapping::pages(5).unwrap();
+        let start = mapping.as_ptr() as usize;
+        // Keep the kernel from faulting in more than one page at a time.
+        unsafe { ::advise(start..start + mapping.len(), ::Advice::NoHugePage) }.ok();
+        mapping[page_size] = 1;
+        mapping[2 * page_size] = 1;
+
+        let residency = residency(start..start + mapping.len()).unwrap();
+        assert_eq!(residency.pages(), 5);
+        assert_eq!(residency.resident_pages(), 2);
+        assert!(residency
+            .runs()
+            .eq([(0..1, false), (1..3, true), (3..5, false)].iter().cloned()));
+    }
+}
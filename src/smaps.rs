@@ -0,0 +1,193 @@
+//! The page size backing individual mappings, from `/proc/self/smaps`.
+
+use core::ops::Range;
+
+use fs::{self, Path};
+use huge::parse_kib;
+use {Error, PageSize};
+
+/// The page sizes of the mapping containing an address, as returned by
+/// [`for_address`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MappedPageSize {
+    /// The address range of the mapping.
+    pub range: Range<usize>,
+    /// The size of the pages the kernel uses for the mapping
+    /// (`KernelPageSize`). This is the huge page size for hugetlb mappings.
+    pub kernel_page_size: PageSize,
+    /// The size of the pages the MMU uses for the mapping (`MMUPageSize`).
+    /// It differs from `kernel_page_size` when the kernel emulates its pages
+    /// with smaller hardware pages.
+    pub mmu_page_size: PageSize,
+    /// The number of bytes of the mapping backed by transparent huge pages
+    /// (`AnonHugePages`).
+    pub anon_huge_pages: usize,
+}
+
+/// Returns the page sizes of the mapping that contains `ptr`, or `None` if
+/// `ptr` is not mapped.
+///
+/// The page size actually backing an address can differ from [`get`](::get),
+/// for example in hugetlb mappings or in mappings backed by transparent huge
+/// pages. This looks the mapping up in `/proc/self/smaps`, which is slow: it
+/// is meant to check the memory one got, not for hot paths.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate page_size;
+///
+/// let value = 0u8;
+/// let mapping = page_size::for_address(&value).unwrap().unwrap();
+/// assert_eq!(mapping.kernel_page_size.bytes(), page_size::get());
+/// ```
+pub fn for_address<T>(ptr: *const T) -> Result<Option<MappedPageSize>, Error> {
+    let mut scan = Scan::new(ptr as usize);
+    fs::for_each_line(&Path::new("/proc/self/smaps"), |line| scan.line(line))?;
+    scan.finish()
+}
+
+/// The search for the mapping containing an address, fed one `smaps` line at
+/// a time.
+struct Scan {
+    address: usize,
+    range: Option<Range<usize>>,
+    kernel_page_size: Option<usize>,
+    mmu_page_size: Option<usize>,
+    anon_huge_pages: Option<usize>,
+}
+
+impl Scan {
+    fn new(address: usize) -> Scan {
+        Scan {
+            address,
+            range: None,
+            kernel_page_size: None,
+            mmu_page_size: None,
+            anon_huge_pages: None,
+        }
+    }
+
+    /// Processes `line`, returning `false` once the mapping has been read.
+    fn line(&mut self, line: &[u8]) -> bool {
+        if let Some(range) = parse_header(line) {
+            if self.range.is_some() {
+                return false;
+            }
+            if range.contains(&self.address) {
+                self.range = Some(range);
+            }
+            return true;
+        }
+        if self.range.is_none() {
+            return true;
+        }
+
+        let colon = match line.iter().position(|&b| b == b':') {
+            Some(colon) => colon,
+            None => return true,
+        };
+        let field = match &line[..colon] {
+            b"KernelPageSize" => &mut self.kernel_page_size,
+            b"MMUPageSize" => &mut self.mmu_page_size,
+            b"AnonHugePages" => &mut self.anon_huge_pages,
+            _ => return true,
+        };
+        *field = parse_kib(&line[colon + 1..]);
+        true
+    }
+
+    fn finish(self) -> Result<Option<MappedPageSize>, Error> {
+        let range = match self.range {
+            Some(range) => range,
+            None => return Ok(None),
+        };
+
+        let malformed = Error::Malformed {
+            what: "/proc/self/smaps",
+        };
+        Ok(Some(MappedPageSize {
+            range,
+            kernel_page_size: self
+                .kernel_page_size
+                .and_then(PageSize::new)
+                .ok_or(malformed)?,
+            mmu_page_size: self
+                .mmu_page_size
+                .and_then(PageSize::new)
+                .ok_or(malformed)?,
+            // Not reported for every kind of mapping by older kernels.
+            anon_huge_pages: self.anon_huge_pages.unwrap_or(0),
+        }))
+    }
+}
+
+/// Parses the address range of a mapping header line, such as
+/// `7ffe78d40000-7ffe78d61000 rw-p 00000000 00:00 0 [stack]`. Returns `None`
+/// for field lines.
+fn parse_header(line: &[u8]) -> Option<Range<usize>> {
+    let end = line.iter().position(|&b| b == b' ')?;
+    let dash = line[..end].iter().position(|&b| b == b'-')?;
+    Some(parse_address(&line[..dash])?..parse_address(&line[dash + 1..end])?)
+}
+
+fn parse_address(digits: &[u8]) -> Option<usize> {
+    fs::parse_hex(digits)
+        .filter(|&address| address <= usize::MAX as u64)
+        .map(|address| address as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SMAPS: &[&[u8]] = &[
+        b"55d0c0a00000-55d0c0a21000 rw-p 00000000 00:00 0    [heap]",
+        b"Size:                132 kB",
+        b"KernelPageSize:        4 kB",
+        b"MMUPageSize:           4 kB",
+        b"AnonHugePages:         0 kB",
+        b"VmFlags: rd wr mr mw me ac ",
+        b"7f0000000000-7f0000400000 rw-s 00000000 00:10 1234 /anon_hugepage (deleted)",
+        b"Size:               4096 kB",
+        b"KernelPageSize:     2048 kB",
+        b"MMUPageSize:        2048 kB",
+        b"AnonHugePages:         0 kB",
+        b"VmFlags: rd wr sh mr mw me ms de ht ",
+    ];
+
+    fn scan(address: usize) -> Result<Option<MappedPageSize>, Error> {
+        let mut scan = Scan::new(address);
+        for line in SMAPS {
+            if !scan.line(line) {
+                break;
+            }
+        }
+        scan.finish()
+    }
+
+    #[test]
+    fn test_scan() {
+        let heap = scan(0x55d0c0a00000).unwrap().unwrap();
+        assert_eq!(heap.range, 0x55d0c0a00000..0x55d0c0a21000);
+        assert_eq!(heap.kernel_page_size.bytes(), 4096);
+
+        let huge = scan(0x7f00003fffff).unwrap().unwrap();
+        assert_eq!(huge.kernel_page_size.bytes(), 2 << 20);
+        assert_eq!(huge.mmu_page_size.bytes(), 2 << 20);
+        assert_eq!(huge.anon_huge_pages, 0);
+
+        assert_eq!(scan(0x7f0000400000), Ok(None));
+        assert_eq!(parse_header(b"KernelPageSize:        4 kB"), None);
+    }
+
+    #[test]
+    fn test_for_address() {
+        let value = 0u8;
+        let mapping = for_address(&value).unwrap().unwrap();
+        assert!(mapping.range.contains(&(&value as *const u8 as usize)));
+        assert_eq!(mapping.kernel_page_size.bytes(), ::get());
+
+        assert_eq!(for_address(8 as *const u8), Ok(None));
+    }
+}
@@ -0,0 +1,285 @@
+//! Splitting byte ranges and slices at page boundaries.
+
+use core::iter::FusedIterator;
+use core::ops::Range;
+use core::slice::{ChunksExact, ChunksExactMut};
+
+use {Align, PageSize};
+
+/// A byte range split at page boundaries, as returned by [`split_pages`].
+///
+/// The three parts are contiguous: `head.end == body.start` and
+/// `body.end == tail.start`. If the range does not contain a whole page, all
+/// of it is in `head`, and `body` and `tail` are empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageSplit {
+    /// The bytes before the first page boundary.
+    pub head: Range<usize>,
+    /// The whole pages.
+    pub body: Range<usize>,
+    /// The bytes after the last page boundary.
+    pub tail: Range<usize>,
+    page_size: PageSize,
+}
+
+impl PageSplit {
+    /// Returns an iterator over the pages in `body`.
+    #[inline]
+    pub fn pages(&self) -> Pages {
+        Pages {
+            range: self.body.clone(),
+            page_size: self.page_size,
+        }
+    }
+}
+
+/// An iterator over the pages of a page-aligned range, as returned by
+/// [`PageSplit::pages`].
+#[derive(Debug, Clone)]
+pub struct Pages {
+    range: Range<usize>,
+    page_size: PageSize,
+}
+
+impl Iterator for Pages {
+    type Item = Range<usize>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Range<usize>> {
+        if self.range.start == self.range.end {
+            return None;
+        }
+        let start = self.range.start;
+        self.range.start += self.page_size.bytes();
+        Some(start..self.range.start)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.len();
+        (len, Some(len))
+    }
+}
+
+impl DoubleEndedIterator for Pages {
+    #[inline]
+    fn next_back(&mut self) -> Option<Range<usize>> {
+        if self.range.start == self.range.end {
+            return None;
+        }
+        let end = self.range.end;
+        self.range.end -= self.page_size.bytes();
+        Some(self.range.end..end)
+    }
+}
+
+impl ExactSizeIterator for Pages {
+    #[inline]
+    fn len(&self) -> usize {
+        (self.range.end - self.range.start) / self.page_size
+    }
+}
+
+impl FusedIterator for Pages {}
+
+impl PageSize {
+    /// Splits `range` into its unaligned head, whole pages and unaligned
+    /// tail.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate page_size;
+    /// let page_size = page_size::PageSize::new(4096).unwrap();
+    /// let split = page_size.split(100..10000);
+    /// assert_eq!(split.head, 100..4096);
+    /// assert_eq!(split.body, 4096..8192);
+    /// assert_eq!(split.tail, 8192..10000);
+    /// assert_eq!(split.pages().collect::<Vec<_>>(), [4096..8192]);
+    /// ```
+    pub fn split(self, range: Range<usize>) -> PageSplit {
+        let start = range.start;
+        let end = range.end.max(start);
+        let body_start = start.page_align_up(self).unwrap_or(end);
+        let body_end = end.page_align_down(self);
+
+        if body_start < body_end {
+            PageSplit {
+                head: start..body_start,
+                body: body_start..body_end,
+                tail: body_end..end,
+                page_size: self,
+            }
+        } else {
+            PageSplit {
+                head: start..end,
+                body: end..end,
+                tail: end..end,
+                page_size: self,
+            }
+        }
+    }
+}
+
+/// Splits `range` at the boundaries of the system's pages.
+///
+/// See [`PageSize::split`].
+#[inline]
+pub fn split_pages(range: Range<usize>) -> PageSplit {
+    PageSize::get().split(range)
+}
+
+/// A byte slice split at page boundaries, as returned by
+/// [`SplitPagesExt::split_pages`].
+#[derive(Debug, Clone, Copy)]
+pub struct SlicePages<'a> {
+    /// The bytes before the first page boundary.
+    pub head: &'a [u8],
+    /// The whole pages.
+    pub body: &'a [u8],
+    /// The bytes after the last page boundary.
+    pub tail: &'a [u8],
+    page_size: PageSize,
+}
+
+impl<'a> SlicePages<'a> {
+    /// Returns an iterator over the pages in `body`.
+    #[inline]
+    pub fn pages(&self) -> ChunksExact<'a, u8> {
+        self.body.chunks_exact(self.page_size.bytes())
+    }
+}
+
+/// A mutable byte slice split at page boundaries, as returned by
+/// [`SplitPagesExt::split_pages_mut`].
+#[derive(Debug)]
+pub struct SlicePagesMut<'a> {
+    /// The bytes before the first page boundary.
+    pub head: &'a mut [u8],
+    /// The whole pages.
+    pub body: &'a mut [u8],
+    /// The bytes after the last page boundary.
+    pub tail: &'a mut [u8],
+    page_size: PageSize,
+}
+
+impl<'a> SlicePagesMut<'a> {
+    /// Returns an iterator over the pages in `body`.
+    #[inline]
+    pub fn pages(self) -> ChunksExactMut<'a, u8> {
+        self.body.chunks_exact_mut(self.page_size.bytes())
+    }
+}
+
+/// Splitting byte slices at the boundaries of the system's pages.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate page_size;
+/// use page_size::SplitPagesExt;
+///
+/// let buf = vec![0u8; 3 * page_size::get()];
+/// let split = buf[1..].split_pages();
+/// assert_eq!(split.head.len() + split.body.len() + split.tail.len(), buf.len() - 1);
+/// assert!(split.pages().all(|page| page.len() == page_size::get()));
+/// ```
+pub trait SplitPagesExt {
+    /// Splits the slice into its unaligned head, whole pages and unaligned
+    /// tail.
+    fn split_pages(&self) -> SlicePages<'_>;
+
+    /// Splits the slice into its unaligned head, whole pages and unaligned
+    /// tail.
+    fn split_pages_mut(&mut self) -> SlicePagesMut<'_>;
+}
+
+impl SplitPagesExt for [u8] {
+    fn split_pages(&self) -> SlicePages<'_> {
+        let page_size = PageSize::get();
+        let (head, body) = split_lengths(self, page_size);
+        let (head, rest) = self.split_at(head);
+        let (body, tail) = rest.split_at(body);
+        SlicePages {
+            head,
+            body,
+            tail,
+            page_size,
+        }
+    }
+
+    fn split_pages_mut(&mut self) -> SlicePagesMut<'_> {
+        let page_size = PageSize::get();
+        let (head, body) = split_lengths(self, page_size);
+        let (head, rest) = self.split_at_mut(head);
+        let (body, tail) = rest.split_at_mut(body);
+        SlicePagesMut {
+            head,
+            body,
+            tail,
+            page_size,
+        }
+    }
+}
+
+// Returns the lengths of the head and body of `slice`.
+fn split_lengths(slice: &[u8], page_size: PageSize) -> (usize, usize) {
+    let start = slice.as_ptr() as usize;
+    let split = page_size.split(start..start + slice.len());
+    (split.head.len(), split.body.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_range() {
+        let page_size = PageSize::new(4096).unwrap();
+
+        let split = page_size.split(0..8192);
+        assert_eq!(
+            (split.head, split.body.clone(), split.tail),
+            (0..0, 0..8192, 8192..8192)
+        );
+        let pages = page_size.split(0..8192).pages();
+        assert_eq!(pages.len(), 2);
+        assert!(pages.rev().eq([4096..8192, 0..4096].iter().cloned()));
+
+        let split = page_size.split(100..200);
+        assert_eq!(
+            (split.head, split.body, split.tail),
+            (100..200, 200..200, 200..200)
+        );
+
+        let split = page_size.split(4096..5000);
+        assert_eq!(
+            (split.head, split.body, split.tail),
+            (4096..5000, 5000..5000, 5000..5000)
+        );
+
+        let split = page_size.split(usize::MAX - 10..usize::MAX);
+        assert_eq!(split.head, usize::MAX - 10..usize::MAX);
+        assert_eq!(split.pages().count(), 0);
+    }
+
+    #[test]
+    fn test_split_slice() {
+        let page_size = ::get();
+        let mut buf = [0u8; 1 << 18];
+        let buf = &mut buf[..4 * page_size];
+
+        let split = buf[7..buf.len() - 7].split_pages();
+        assert_eq!(
+            split.head.len() + split.body.len() + split.tail.len(),
+            buf.len() - 14
+        );
+        assert_eq!(split.body.as_ptr() as usize % page_size, 0);
+        assert!(split.pages().count() >= 2);
+
+        for page in buf[1..].split_pages_mut().pages() {
+            page[0] = 1;
+        }
+        assert!(buf.iter().filter(|&&byte| byte == 1).count() >= 3);
+    }
+}
@@ -0,0 +1,296 @@
+//! Transparent Huge Page policy.
+//!
+//! With transparent huge pages (THP), the kernel backs anonymous memory with
+//! huge pages on its own, without hugetlbfs. Whether it does so for every
+//! mapping or only for those marked with `madvise(MADV_HUGEPAGE)` is a system
+//! setting under `/sys/kernel/mm/transparent_hugepage`, which this module
+//! reads.
+//!
+//! # Example
+//!
+//! ```rust
+//! extern crate page_size;
+//! use page_size::thp::Mode;
+//!
+//! match page_size::thp::mode().unwrap() {
+//!     Mode::Always => println!("huge pages by default"),
+//!     Mode::Madvise => println!("huge pages with MADV_HUGEPAGE"),
+//!     _ => println!("no transparent huge pages"),
+//! }
+//! ```
+
+#[cfg(any(not(feature = "no_std"), feature = "alloc"))]
+use alloc::vec::Vec;
+
+use fs::{self, Path};
+use huge;
+use unix::sys;
+use {Error, PageSize};
+
+/// The directory of the THP settings.
+pub const THP_DIR: &str = "/sys/kernel/mm/transparent_hugepage";
+
+/// When the kernel backs anonymous mappings with transparent huge pages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Mode {
+    /// For every mapping, unless disabled with `madvise(MADV_NOHUGEPAGE)`.
+    Always,
+    /// Only for mappings marked with `madvise(MADV_HUGEPAGE)`.
+    Madvise,
+    /// Never.
+    Never,
+}
+
+impl Mode {
+    fn parse(word: &[u8]) -> Option<Mode> {
+        match word {
+            b"always" => Some(Mode::Always),
+            b"madvise" => Some(Mode::Madvise),
+            b"never" => Some(Mode::Never),
+            _ => None,
+        }
+    }
+}
+
+/// The THP setting of a single huge page size (multi-size THP).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum SizeMode {
+    /// Use the size for every mapping.
+    Always,
+    /// Follow the system-wide [`mode`].
+    Inherit,
+    /// Use the size only for mappings marked with `madvise(MADV_HUGEPAGE)`.
+    Madvise,
+    /// Never use the size.
+    Never,
+}
+
+impl SizeMode {
+    /// Returns the mode in effect for the size, given the system-wide `mode`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate page_size;
+    /// use page_size::thp::{Mode, SizeMode};
+    ///
+    /// assert_eq!(SizeMode::Inherit.resolve(Mode::Madvise), Mode::Madvise);
+    /// assert_eq!(SizeMode::Never.resolve(Mode::Always), Mode::Never);
+    /// ```
+    pub fn resolve(self, mode: Mode) -> Mode {
+        match self {
+            SizeMode::Always => Mode::Always,
+            SizeMode::Inherit => mode,
+            SizeMode::Madvise => Mode::Madvise,
+            SizeMode::Never => Mode::Never,
+        }
+    }
+
+    fn parse(word: &[u8]) -> Option<SizeMode> {
+        match word {
+            b"always" => Some(SizeMode::Always),
+            b"inherit" => Some(SizeMode::Inherit),
+            b"madvise" => Some(SizeMode::Madvise),
+            b"never" => Some(SizeMode::Never),
+            _ => None,
+        }
+    }
+}
+
+/// How hard the kernel tries to make a huge page available when a page fault
+/// cannot get one right away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Defrag {
+    /// Stall the fault to reclaim and compact memory.
+    Always,
+    /// Wake kswapd and kcompactd, and fall back to base pages.
+    Defer,
+    /// Stall for `madvise(MADV_HUGEPAGE)` mappings, defer for the others.
+    DeferMadvise,
+    /// Stall for `madvise(MADV_HUGEPAGE)` mappings, fall back to base pages
+    /// for the others.
+    Madvise,
+    /// Fall back to base pages.
+    Never,
+}
+
+impl Defrag {
+    fn parse(word: &[u8]) -> Option<Defrag> {
+        match word {
+            b"always" => Some(Defrag::Always),
+            b"defer" => Some(Defrag::Defer),
+            b"defer+madvise" => Some(Defrag::DeferMadvise),
+            b"madvise" => Some(Defrag::Madvise),
+            b"never" => Some(Defrag::Never),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the system-wide THP mode, from
+/// `/sys/kernel/mm/transparent_hugepage/enabled`.
+///
+/// Kernels built without THP support report [`Mode::Never`].
+pub fn mode() -> Result<Mode, Error> {
+    let mut path = Path::new(THP_DIR);
+    path.push(b"/enabled");
+    read_selected(&path, "transparent_hugepage/enabled", Mode::parse)
+        .map(|mode| mode.unwrap_or(Mode::Never))
+}
+
+/// Returns the THP defrag policy, from
+/// `/sys/kernel/mm/transparent_hugepage/defrag`.
+///
+/// Kernels built without THP support report [`Defrag::Never`].
+pub fn defrag() -> Result<Defrag, Error> {
+    let mut path = Path::new(THP_DIR);
+    path.push(b"/defrag");
+    read_selected(&path, "transparent_hugepage/defrag", Defrag::parse)
+        .map(|defrag| defrag.unwrap_or(Defrag::Never))
+}
+
+/// Returns the size of the huge pages mapped by a single page middle
+/// directory entry (2 MiB on x86_64), from
+/// `/sys/kernel/mm/transparent_hugepage/hpage_pmd_size`.
+///
+/// This is the size `khugepaged` collapses mappings into. It is `None` if
+/// the kernel was built without THP support.
+pub fn pmd_size() -> Result<Option<PageSize>, Error> {
+    let mut path = Path::new(THP_DIR);
+    path.push(b"/hpage_pmd_size");
+    match fs::read_usize(&path, "transparent_hugepage/hpage_pmd_size") {
+        Ok(bytes) => PageSize::new(bytes).map(Some).ok_or(Error::Malformed {
+            what: "transparent_hugepage/hpage_pmd_size",
+        }),
+        Err(Error::Os {
+            errno: sys::ENOENT, ..
+        }) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Returns the setting of every multi-size THP size, in increasing order of
+/// size.
+///
+/// The sizes are those of the `hugepages-<size>kB` directories under
+/// `/sys/kernel/mm/transparent_hugepage`. The list is empty on kernels
+/// without multi-size THP (before Linux 6.8).
+///
+/// # Example
+///
+/// ```rust
+/// extern crate page_size;
+///
+/// let mode = page_size::thp::mode().unwrap();
+/// for (size, size_mode) in page_size::thp::size_modes().unwrap() {
+///     println!("{} KiB: {:?}", size.bytes() / 1024, size_mode.resolve(mode));
+/// }
+/// ```
+#[cfg(any(not(feature = "no_std"), feature = "alloc"))]
+pub fn size_modes() -> Result<Vec<(PageSize, SizeMode)>, Error> {
+    let mut modes = Vec::new();
+    for_each_size_mode(|size, mode| modes.push((size, mode)))?;
+    modes.sort_by_key(|&(size, _)| size);
+
+    Ok(modes)
+}
+
+/// Calls `f` with the setting of every multi-size THP size, in no particular
+/// order.
+///
+/// This is the allocation-free version of `size_modes`.
+pub fn for_each_size_mode<F>(mut f: F) -> Result<(), Error>
+where
+    F: FnMut(PageSize, SizeMode),
+{
+    let dir = Path::new(THP_DIR);
+    let mut result = Ok(());
+    huge::for_each_size_dir(&dir, |size, name| {
+        if result.is_err() {
+            return;
+        }
+        let mut path = dir.join(b"/");
+        path.push(name).push(b"/enabled");
+        result = match read_selected(
+            &path,
+            "transparent_hugepage/hugepages-*/enabled",
+            SizeMode::parse,
+        ) {
+            Ok(Some(mode)) => {
+                f(size, mode);
+                Ok(())
+            }
+            // The size disappeared, or has no `enabled` control.
+            Ok(None) => Ok(()),
+            Err(err) => Err(err),
+        };
+    })?;
+
+    result
+}
+
+/// Reads a setting file listing the choices with the selected one in brackets,
+/// such as `always [madvise] never`. A missing file is reported as `None`.
+fn read_selected<T, P>(path: &Path, what: &'static str, parse: P) -> Result<Option<T>, Error>
+where
+    P: FnOnce(&[u8]) -> Option<T>,
+{
+    let mut buf = [0u8; 128];
+    let contents = match fs::read(path, &mut buf) {
+        Ok(contents) => contents,
+        Err(Error::Os {
+            errno: sys::ENOENT, ..
+        }) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    selected(contents)
+        .and_then(parse)
+        .map(Some)
+        .ok_or(Error::Malformed { what })
+}
+
+/// Returns the bracketed word of a setting file.
+fn selected(contents: &[u8]) -> Option<&[u8]> {
+    let start = contents.iter().position(|&b| b == b'[')? + 1;
+    let len = contents[start..].iter().position(|&b| b == b']')?;
+    Some(&contents[start..start + len])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selected() {
+        assert_eq!(selected(b"always [madvise] never\n"), Some(&b"madvise"[..]));
+        assert_eq!(
+            selected(b"always defer [defer+madvise] madvise never"),
+            Some(&b"defer+madvise"[..])
+        );
+        assert_eq!(selected(b"always madvise never"), None);
+        assert_eq!(Defrag::parse(b"defer+madvise"), Some(Defrag::DeferMadvise));
+        assert_eq!(SizeMode::parse(b"inherit"), Some(SizeMode::Inherit));
+        assert_eq!(Mode::parse(b"inherit"), None);
+    }
+
+    #[test]
+    fn test_settings() {
+        let mode = mode().unwrap();
+        defrag().unwrap();
+
+        match pmd_size().unwrap() {
+            Some(size) => assert!(size.bytes() > ::get()),
+            None => assert_eq!(mode, Mode::Never),
+        }
+
+        for_each_size_mode(|size, size_mode| {
+            assert!(size.bytes() > ::get());
+            size_mode.resolve(mode);
+        })
+        .unwrap();
+    }
+}